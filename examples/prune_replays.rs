@@ -0,0 +1,40 @@
+//! Deletes the replay files in the directories given as arguments which were not used since
+//! the last prune, e. g. after running the tests with usage tracking enabled:
+//! `cargo run --example prune_replays -- --dry-run tests/replays`.
+
+extern crate reqwest_mock;
+
+use reqwest_mock::client::RecordingTarget;
+use std::env;
+use std::process;
+
+fn main() {
+    let mut dry_run = false;
+    let mut dirs = Vec::new();
+    for arg in env::args().skip(1) {
+        if arg == "--dry-run" {
+            dry_run = true;
+        } else {
+            dirs.push(arg);
+        }
+    }
+    if dirs.is_empty() {
+        eprintln!("usage: prune_replays [--dry-run] <dir>...");
+        process::exit(2);
+    }
+
+    for dir in dirs {
+        match RecordingTarget::dir(&dir).prune(dry_run) {
+            Ok(files) => {
+                for file in files {
+                    let action = if dry_run { "would delete" } else { "deleted" };
+                    println!("{} {}", action, file.display());
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", dir, e);
+                process::exit(1);
+            }
+        }
+    }
+}
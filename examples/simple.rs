@@ -2,8 +2,8 @@ extern crate reqwest_mock;
 
 use reqwest_mock::client::*;
 
-const URL: &'static str = "https://httpbin.org/uuid";
-const URL2: &'static str = "https://httpbin.org/uuid#";
+const URL: &str = "https://httpbin.org/uuid";
+const URL2: &str = "https://httpbin.org/uuid#";
 
 fn perform_request<C: Client>(client: &C, url: &str) -> String {
     // This method is just a placeholder for some fancy computations.
@@ -0,0 +1,26 @@
+//! Upgrades the replay files in the directories given as arguments to the current format
+//! version, e. g. `cargo run --example upgrade_replays -- tests/replays`.
+
+extern crate reqwest_mock;
+
+use reqwest_mock::client::RecordingTarget;
+use std::env;
+use std::process;
+
+fn main() {
+    let dirs: Vec<String> = env::args().skip(1).collect();
+    if dirs.is_empty() {
+        eprintln!("usage: upgrade_replays <dir>...");
+        process::exit(2);
+    }
+
+    for dir in dirs {
+        match RecordingTarget::dir(&dir).upgrade() {
+            Ok(count) => println!("{}: upgraded {} replay files", dir, count),
+            Err(e) => {
+                eprintln!("{}: {}", dir, e);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! A drop-in replacement for the client of `reqwest::blocking`.
+//!
+//! The types of this module mirror the methods of `reqwest::blocking::Client`,
+//! `RequestBuilder` and `Response`, so existing code can switch to them by changing an
+//! import. They are backed by any [Client](../client/trait.Client.html), `DirectClient` by
+//! default, so tests can swap in a `ReplayClient` or `StubClient` without touching the call
+//! sites.
+//!
+//! Methods return this crate's `Error` instead of `reqwest::Error`, and redirects are
+//! configured with a `RedirectPolicy` instead of `reqwest::redirect::Policy`.
+//!
+//! # Examples
+//!
+//! ```
+//! // Was: use reqwest::blocking::Client;
+//! use reqwest_mock::blocking::Client;
+//! use reqwest_mock::{Error, Method, StubClient, StubDefault, StubSettings, StubStrictness, Url};
+//!
+//! fn get_greeting<C: reqwest_mock::Client>(client: &Client<C>) -> Result<String, Error> {
+//!     client
+//!         .get("http://example.com/greeting")
+//!         .header("accept", "text/plain")
+//!         .send()?
+//!         .error_for_status()?
+//!         .text()
+//! }
+//!
+//! let mut stub = StubClient::new(StubSettings {
+//!     default: StubDefault::Error,
+//!     strictness: StubStrictness::MethodUrl,
+//! });
+//! stub.stub(Url::parse("http://example.com/greeting").unwrap())
+//!     .method(Method::GET)
+//!     .response()
+//!     .body("Hello")
+//!     .mock()
+//!     .unwrap();
+//!
+//! assert_eq!(get_greeting(&Client::from(stub)).unwrap(), "Hello");
+//! ```
+
+use body::Body;
+use bytes::Bytes;
+use client::{self, DirectClient};
+use config::{ClientConfig, RedirectPolicy};
+use error::{Error, ErrorKind};
+use multipart::Form;
+use request::Request;
+use request_builder::PendingRequest;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{tls, Certificate, Identity, IntoUrl, Method, StatusCode, Url};
+use response::StreamingResponse;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A client with the methods of `reqwest::blocking::Client`, performing its requests
+/// through the wrapped `Client`.
+///
+/// Cloning it is cheap, the clones share the wrapped client.
+pub struct Client<C: client::Client = DirectClient> {
+    inner: Arc<C>,
+}
+
+impl Client<DirectClient> {
+    /// Create a client performing real requests with the default configuration.
+    pub fn new() -> Self {
+        Client::from(DirectClient::new())
+    }
+
+    /// Create a builder to configure a client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+}
+
+impl Default for Client<DirectClient> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: client::Client> Client<C> {
+    /// Start building a `GET` request.
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder<C> {
+        self.request(Method::GET, url)
+    }
+
+    /// Start building a `POST` request.
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder<C> {
+        self.request(Method::POST, url)
+    }
+
+    /// Start building a `PUT` request.
+    pub fn put<U: IntoUrl>(&self, url: U) -> RequestBuilder<C> {
+        self.request(Method::PUT, url)
+    }
+
+    /// Start building a `PATCH` request.
+    pub fn patch<U: IntoUrl>(&self, url: U) -> RequestBuilder<C> {
+        self.request(Method::PATCH, url)
+    }
+
+    /// Start building a `DELETE` request.
+    pub fn delete<U: IntoUrl>(&self, url: U) -> RequestBuilder<C> {
+        self.request(Method::DELETE, url)
+    }
+
+    /// Start building a `HEAD` request.
+    pub fn head<U: IntoUrl>(&self, url: U) -> RequestBuilder<C> {
+        self.request(Method::HEAD, url)
+    }
+
+    /// Start building a request with the given method.
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder<C> {
+        RequestBuilder {
+            request: PendingRequest::new(self.inner.config(), url, method),
+            client: self.clone(),
+        }
+    }
+
+    /// Perform a request.
+    pub fn execute(&self, request: Request) -> Result<Response, Error> {
+        self.inner
+            .execute_streaming(None, request)
+            .map(Response::from)
+    }
+
+    /// The wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: client::Client> Clone for Client<C> {
+    fn clone(&self) -> Self {
+        Client {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C: client::Client> From<C> for Client<C> {
+    fn from(client: C) -> Self {
+        Client {
+            inner: Arc::new(client),
+        }
+    }
+}
+
+impl<C: client::Client> fmt::Debug for Client<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("config", self.inner.config())
+            .finish()
+    }
+}
+
+/// A builder with the methods of `reqwest::blocking::ClientBuilder`.
+///
+/// `build` creates a client performing real requests, `build_with` applies the configuration
+/// to another client, like a `ReplayClient` or `StubClient`.
+#[must_use]
+pub struct ClientBuilder {
+    config: ClientConfig,
+
+    /// An error which occurred while configuring the client, returned by `build`.
+    error: Option<Error>,
+}
+
+impl ClientBuilder {
+    /// Create a builder with the default configuration, like `Client::builder`.
+    pub fn new() -> Self {
+        ClientBuilder {
+            config: ClientConfig::default(),
+            error: None,
+        }
+    }
+
+    /// Set the `User-Agent` header of every request.
+    pub fn user_agent<V>(mut self, value: V) -> Self
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<::http::Error>,
+    {
+        match HeaderValue::try_from(value) {
+            Ok(value) => self.config.user_agent = Some(value),
+            Err(e) => self.set_error(e.into().into()),
+        }
+        self
+    }
+
+    /// Set headers included in every request.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.config.default_headers.extend(headers);
+        self
+    }
+
+    /// Enable or disable automatic gzip decompression.
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.config.gzip = enable;
+        self
+    }
+
+    /// Set the redirect policy.
+    pub fn redirect(mut self, policy: RedirectPolicy) -> Self {
+        self.config.redirect = policy;
+        self
+    }
+
+    /// Enable or disable automatic setting of the `Referer` header when following redirects.
+    pub fn referer(mut self, enable: bool) -> Self {
+        self.config.referer = enable;
+        self
+    }
+
+    /// Set the timeout of requests, or none to disable it.
+    pub fn timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+        self.config.timeout = timeout.into();
+        self
+    }
+
+    /// Set the timeout for establishing connections, or none to disable it.
+    pub fn connect_timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+        self.config.connect_timeout = timeout.into();
+        self
+    }
+
+    /// Trust an additional root certificate.
+    pub fn add_root_certificate(mut self, cert: Certificate) -> Self {
+        self.config.root_certificates.push(cert);
+        self
+    }
+
+    /// Present a client certificate to servers requesting mutual TLS.
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.config.identity = Some(identity);
+        self
+    }
+
+    /// Set the minimum TLS version to accept.
+    pub fn min_tls_version(mut self, version: tls::Version) -> Self {
+        self.config.min_tls_version = Some(version);
+        self
+    }
+
+    /// Accept invalid server certificates, see `ClientConfig::danger_accept_invalid_certs`.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.config.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    /// Create a client performing real requests.
+    pub fn build(self) -> Result<Client, Error> {
+        self.build_with(DirectClient::new())
+    }
+
+    /// Replace the config of `client` and wrap it.
+    pub fn build_with<C: client::Client>(self, mut client: C) -> Result<Client<C>, Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        *client.config_mut() = self.config;
+        Ok(Client::from(client))
+    }
+
+    /// Remember the first error that occurred.
+    fn set_error(&mut self, error: Error) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A builder with the methods of `reqwest::blocking::RequestBuilder`.
+///
+/// Errors which occur while building the request are returned by `send` and `build`.
+#[must_use]
+pub struct RequestBuilder<C: client::Client = DirectClient> {
+    client: Client<C>,
+    request: PendingRequest,
+}
+
+impl<C: client::Client> RequestBuilder<C> {
+    /// Add a header to the request.
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<::http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<::http::Error>,
+    {
+        let name = HeaderName::try_from(key).map_err(Into::into);
+        let value = HeaderValue::try_from(value).map_err(Into::into);
+        match name.and_then(|name| value.map(|value| (name, value))) {
+            Ok((name, value)) => {
+                self.request.headers.append(name, value);
+            }
+            Err(e) => self.request.set_error(e.into()),
+        }
+        self
+    }
+
+    /// Add multiple headers to the request.
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.request.headers.extend(headers);
+        self
+    }
+
+    /// Enable HTTP basic authentication.
+    pub fn basic_auth<U: Display, P: Display>(mut self, username: U, password: Option<P>) -> Self {
+        self.request.basic_auth(username, password);
+        self
+    }
+
+    /// Enable HTTP bearer authentication.
+    pub fn bearer_auth<T: Display>(mut self, token: T) -> Self {
+        self.request.bearer_auth(token);
+        self
+    }
+
+    /// Set the body of the request.
+    pub fn body<T: Into<Body>>(mut self, body: T) -> Self {
+        self.request.body = Some(body.into());
+        self
+    }
+
+    /// Set a timeout for this request only, overriding the one of the client.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.request.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the body of the request to a `multipart/form-data` form.
+    pub fn multipart(mut self, form: Form) -> Self {
+        self.request.multipart(form);
+        self
+    }
+
+    /// Append the serialized `query` to the query string of the URL.
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.request.query(query);
+        self
+    }
+
+    /// Set the body of the request to `form` serialized as URL encoded form.
+    pub fn form<T: Serialize + ?Sized>(mut self, form: &T) -> Self {
+        self.request.form(form);
+        self
+    }
+
+    /// Set the body of the request to `json` serialized as JSON.
+    pub fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.request.json(json);
+        self
+    }
+
+    /// Build the request, to be performed with `Client::execute`.
+    ///
+    /// Unlike with reqwest, a timeout set on this builder is not part of the request.
+    pub fn build(self) -> Result<Request, Error> {
+        let (request, _) = self.request.build(self.client.inner.config())?;
+        Ok(request)
+    }
+
+    /// Send the request.
+    pub fn send(self) -> Result<Response, Error> {
+        let client = self.client;
+        let (request, config) = self.request.build(client.inner.config())?;
+        client
+            .inner
+            .execute_streaming(config.as_ref(), request)
+            .map(Response::from)
+    }
+
+    /// Copy the builder, unless its body is a file, reader or multipart form, or building
+    /// the request failed already.
+    pub fn try_clone(&self) -> Option<Self> {
+        Some(RequestBuilder {
+            client: self.client.clone(),
+            request: self.request.try_clone()?,
+        })
+    }
+}
+
+/// A response with the methods of `reqwest::blocking::Response`.
+///
+/// The body is read as it is received, reading from the response reads the body.
+#[derive(Debug)]
+pub struct Response {
+    inner: StreamingResponse,
+}
+
+impl Response {
+    /// The status code.
+    pub fn status(&self) -> StatusCode {
+        self.inner.status
+    }
+
+    /// The final url of this response.
+    pub fn url(&self) -> &Url {
+        &self.inner.url
+    }
+
+    /// The headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.inner.headers
+    }
+
+    /// The headers, mutably.
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.inner.headers
+    }
+
+    /// The value of the `Content-Length` header, if it is present and valid.
+    pub fn content_length(&self) -> Option<u64> {
+        ::response::content_length(&self.inner.headers)
+    }
+
+    /// Read the body and decode it as text, see `Response::text` of this crate.
+    pub fn text(self) -> Result<String, Error> {
+        Ok(self.inner.into_response()?.text())
+    }
+
+    /// Read the body and decode it as text, using `default_encoding` if the `Content-Type`
+    /// header doesn't specify a charset.
+    pub fn text_with_charset(self, default_encoding: &str) -> Result<String, Error> {
+        Ok(self
+            .inner
+            .into_response()?
+            .text_with_charset(default_encoding))
+    }
+
+    /// Read the body and deserialize it as JSON.
+    pub fn json<T: DeserializeOwned>(self) -> Result<T, Error> {
+        self.inner.into_response()?.json()
+    }
+
+    /// Read the body.
+    pub fn bytes(self) -> Result<Bytes, Error> {
+        Ok(self.inner.into_response()?.body.into())
+    }
+
+    /// Copy the body to `writer`, returning the number of bytes copied.
+    pub fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<u64, Error> {
+        Ok(io::copy(self, writer)?)
+    }
+
+    /// Turn a response with a client (4xx) or server (5xx) error status into an error,
+    /// carrying the status and body of the response.
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        if self.is_error() {
+            Err(self.inner.into_response()?.error_for_status().unwrap_err())
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Like `error_for_status`, but the body of the error is left empty as it can't be read.
+    pub fn error_for_status_ref(&self) -> Result<&Self, Error> {
+        if self.is_error() {
+            Err(ErrorKind::Status(self.status(), Vec::new()).into())
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        self.status().is_client_error() || self.status().is_server_error()
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl From<StreamingResponse> for Response {
+    fn from(inner: StreamingResponse) -> Self {
+        Response { inner }
+    }
+}
+
+impl From<Response> for StreamingResponse {
+    fn from(response: Response) -> Self {
+        response.inner
+    }
+}
@@ -1,10 +1,15 @@
+use multipart::Form;
+use sha2::{Digest, Sha256};
+use std::fmt;
 use std::fs::File;
-use std::io::{self, Read};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
 
 /// A HTTP request body.
 ///
-/// This is either a file pointer or a memory sequence of bytes.
-/// This distinction only matters when using `DirectClient`, in which case a file
+/// This is either a file pointer, a multipart form, a reader or a memory sequence of bytes.
+/// This distinction only matters when using `DirectClient`, in which case a file or reader
 /// might be read chunked.
 ///
 /// Note that this is **not** the same type as the one found in the `reqwest` crate.
@@ -20,9 +25,68 @@ enum BodyValue {
 
     /// A pointer to a file yet to be read.
     File(File),
+
+    /// A multipart form, whose files are yet to be read.
+    Multipart(Form),
+
+    /// An arbitrary reader yet to be read.
+    Reader(BodyReader),
+}
+
+struct BodyReader {
+    reader: Box<dyn Read + Send>,
+    length: Option<u64>,
+}
+
+impl fmt::Debug for BodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BodyReader")
+            .field("length", &self.length)
+            .finish()
+    }
 }
 
 impl Body {
+    /// Create a body streamed from `reader`.
+    ///
+    /// If the `length` of the body is known it is sent as `Content-Length`, otherwise the
+    /// body is sent chunked.
+    ///
+    /// `ReplayClient` and `StubClient` have to read the whole body to compare it, see
+    /// `ClientConfig::max_buffered_body` for how they avoid keeping large bodies in memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reqwest_mock::Body;
+    /// use std::io::Cursor;
+    ///
+    /// let body = Body::from_reader(Cursor::new(vec![1, 2, 3]), Some(3));
+    /// ```
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, length: Option<u64>) -> Self {
+        Body {
+            value: BodyValue::Reader(BodyReader {
+                reader: Box::new(reader),
+                length,
+            }),
+        }
+    }
+
+    /// The body, if it is kept in memory.
+    ///
+    /// This is not the case for bodies created from a file, reader or multipart form.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self.value {
+            BodyValue::Bytes(ref bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Copy the body, if it is kept in memory.
+    pub(crate) fn try_clone(&self) -> Option<Body> {
+        self.as_bytes().map(|bytes| bytes.to_vec().into())
+    }
+
     // TODO: Consider whether this should be public for everyone.
     pub(crate) fn try_to_vec(self) -> Result<Vec<u8>, io::Error> {
         match self.value {
@@ -32,15 +96,303 @@ impl Body {
                 f.read_to_end(&mut bytes)?;
                 Ok(bytes)
             }
+            BodyValue::Multipart(form) => form.try_to_vec(),
+            BodyValue::Reader(mut r) => {
+                let mut bytes = Vec::new();
+                r.reader.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Return the file, if this body is one.
+    pub(crate) fn into_file(self) -> Result<File, Body> {
+        match self.value {
+            BodyValue::File(file) => Ok(file),
+            value => Err(Body { value }),
+        }
+    }
+
+    /// Read the body for comparing it, keeping it in memory only if it is not longer
+    /// than `limit` bytes.
+    pub(crate) fn into_mem(self, limit: Option<u64>) -> Result<BodyMem, io::Error> {
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return self.try_to_vec().map(BodyMem::Bytes),
+        };
+
+        match self.value {
+            BodyValue::Bytes(bytes) => {
+                if bytes.len() as u64 <= limit {
+                    return Ok(BodyMem::Bytes(bytes));
+                }
+                let mut digest = DigestWriter::new(io::sink());
+                digest.write_all(&bytes)?;
+                Ok(BodyMem::Large(digest.finish(Spooled::Bytes(bytes))))
+            }
+            BodyValue::File(mut file) => {
+                // Files don't have to be copied, they are read again when sending them.
+                let start = file.stream_position()?;
+                let head = read_head(&mut file, limit)?;
+                if head.len() as u64 <= limit {
+                    return Ok(BodyMem::Bytes(head));
+                }
+                let mut digest = DigestWriter::new(io::sink());
+                digest.write_all(&head)?;
+                io::copy(&mut file, &mut digest)?;
+                Ok(BodyMem::Large(
+                    digest.finish(Spooled::File(SharedFile::new(file, start))),
+                ))
+            }
+            BodyValue::Multipart(form) => spool(form.into_reader(), limit),
+            BodyValue::Reader(r) => spool(r.reader, limit),
+        }
+    }
+}
+
+/// Read at most `limit + 1` bytes of `reader`, so the result tells if it is longer than `limit`.
+pub(crate) fn read_head<R: Read>(reader: &mut R, limit: u64) -> Result<Vec<u8>, io::Error> {
+    let mut head = Vec::new();
+    reader
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Read `reader` into memory, or into a temporary file if it is longer than `limit` bytes.
+fn spool<R: Read>(mut reader: R, limit: u64) -> Result<BodyMem, io::Error> {
+    let head = read_head(&mut reader, limit)?;
+    if head.len() as u64 <= limit {
+        return Ok(BodyMem::Bytes(head));
+    }
+
+    let mut digest = DigestWriter::new(::tempfile::tempfile()?);
+    digest.write_all(&head)?;
+    io::copy(&mut reader, &mut digest)?;
+    let file = digest.inner.take().expect("writer taken");
+    Ok(BodyMem::Large(
+        digest.finish(Spooled::File(SharedFile::new(file, 0))),
+    ))
+}
+
+/// Passes everything written to `inner`, computing the digest and length of it.
+struct DigestWriter<W> {
+    inner: Option<W>,
+    hasher: Sha256,
+    length: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    fn new(inner: W) -> Self {
+        DigestWriter {
+            inner: Some(inner),
+            hasher: Sha256::new(),
+            length: 0,
+        }
+    }
+
+    fn finish(self, source: Spooled) -> LargeBody {
+        let digest = self
+            .hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        LargeBody {
+            sha256: digest,
+            length: self.length,
+            source: Some(Arc::new(source)),
+        }
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = match self.inner {
+            Some(ref mut inner) => inner.write(buf)?,
+            None => buf.len(),
+        };
+        self.hasher.update(&buf[..n]);
+        self.length += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner {
+            Some(ref mut inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A request body read by the clients which have to compare requests.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub(crate) enum BodyMem {
+    /// The whole body kept in memory.
+    Bytes(Vec<u8>),
+
+    /// A body exceeding `ClientConfig::max_buffered_body`, identified by its digest.
+    Large(LargeBody),
+}
+
+impl BodyMem {
+    /// The body, if it is kept in memory.
+    pub(crate) fn bytes(&self) -> Option<&[u8]> {
+        match *self {
+            BodyMem::Bytes(ref bytes) => Some(bytes),
+            BodyMem::Large(_) => None,
+        }
+    }
+}
+
+impl From<BodyMem> for Body {
+    fn from(b: BodyMem) -> Body {
+        match b {
+            BodyMem::Bytes(bytes) => bytes.into(),
+            BodyMem::Large(large) => {
+                let length = large.length;
+                Body::from_reader(
+                    LargeBodyReader {
+                        source: large.source,
+                        position: 0,
+                        length,
+                    },
+                    Some(length),
+                )
+            }
+        }
+    }
+}
+
+/// A body too large to be kept in memory.
+///
+/// Two large bodies are considered equal if their digest and length are.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct LargeBody {
+    /// The SHA-256 digest of the body, hex encoded.
+    pub sha256: String,
+    /// The length of the body in bytes.
+    pub length: u64,
+    /// Where the body can be read again to send it, missing if it was loaded from a
+    /// replay file.
+    #[serde(skip)]
+    source: Option<Arc<Spooled>>,
+}
+
+impl fmt::Debug for LargeBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LargeBody")
+            .field("sha256", &self.sha256)
+            .field("length", &self.length)
+            .finish()
+    }
+}
+
+impl PartialEq for LargeBody {
+    fn eq(&self, other: &LargeBody) -> bool {
+        self.sha256 == other.sha256 && self.length == other.length
+    }
+}
+
+impl Eq for LargeBody {}
+
+impl Hash for LargeBody {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sha256.hash(state);
+        self.length.hash(state);
+    }
+}
+
+enum Spooled {
+    Bytes(Vec<u8>),
+    File(SharedFile),
+}
+
+/// Reads a `LargeBody` from its source, which is shared between clones of the body.
+struct LargeBodyReader {
+    source: Option<Arc<Spooled>>,
+    position: u64,
+    length: u64,
+}
+
+impl Read for LargeBodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length - self.position;
+        let max = buf.len().min(remaining as usize);
+        let n = match self.source.as_deref() {
+            Some(Spooled::Bytes(bytes)) => {
+                (&bytes[self.position as usize..]).read(&mut buf[..max])?
+            }
+            Some(Spooled::File(file)) => file.read_at(self.position, &mut buf[..max])?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "the body of a replayed request can't be read again",
+                ))
+            }
+        };
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+/// A file read by any number of readers, each starting at the position `start`.
+pub(crate) struct SharedFile {
+    file: Mutex<File>,
+    start: u64,
+}
+
+impl SharedFile {
+    pub(crate) fn new(file: File, start: u64) -> Self {
+        SharedFile {
+            file: Mutex::new(file),
+            start,
+        }
+    }
+
+    /// Read from `position` bytes after the start.
+    fn read_at(&self, position: u64, buf: &mut [u8]) -> io::Result<usize> {
+        // Seek every time, the file might have been read by another reader in the meantime.
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.seek(SeekFrom::Start(self.start + position))?;
+        file.read(buf)
+    }
+
+    /// Return a new reader reading the file from the start.
+    pub(crate) fn reader(file: &Arc<SharedFile>) -> SharedFileReader {
+        SharedFileReader {
+            file: file.clone(),
+            position: 0,
         }
     }
 }
 
+pub(crate) struct SharedFileReader {
+    file: Arc<SharedFile>,
+    position: u64,
+}
+
+impl Read for SharedFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read_at(self.position, buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
 impl From<Body> for ::reqwest::blocking::Body {
     fn from(b: Body) -> ::reqwest::blocking::Body {
         match b.value {
             BodyValue::Bytes(b) => b.into(),
             BodyValue::File(f) => f.into(),
+            BodyValue::Multipart(form) => match form.content_length() {
+                Some(length) => ::reqwest::blocking::Body::sized(form.into_reader(), length),
+                None => ::reqwest::blocking::Body::new(form.into_reader()),
+            },
+            BodyValue::Reader(r) => match r.length {
+                Some(length) => ::reqwest::blocking::Body::sized(r.reader, length),
+                None => ::reqwest::blocking::Body::new(r.reader),
+            },
         }
     }
 }
@@ -49,7 +401,7 @@ impl From<Vec<u8>> for Body {
     #[inline]
     fn from(v: Vec<u8>) -> Self {
         Body {
-            value: BodyValue::Bytes(v.into()),
+            value: BodyValue::Bytes(v),
         }
     }
 }
@@ -89,3 +441,76 @@ impl From<File> for Body {
         }
     }
 }
+
+impl From<Form> for Body {
+    #[inline]
+    fn from(f: Form) -> Self {
+        Body {
+            value: BodyValue::Multipart(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn bytes() -> Vec<u8> {
+        (0..100u8).collect()
+    }
+
+    fn large(body: BodyMem) -> LargeBody {
+        match body {
+            BodyMem::Large(large) => large,
+            BodyMem::Bytes(_) => panic!("body was kept in memory"),
+        }
+    }
+
+    #[test]
+    fn into_mem_within_limit() {
+        let body = Body::from_reader(Cursor::new(bytes()), None);
+        assert_eq!(body.into_mem(Some(100)).unwrap(), BodyMem::Bytes(bytes()));
+
+        let body = Body::from(bytes());
+        assert_eq!(body.into_mem(None).unwrap(), BodyMem::Bytes(bytes()));
+    }
+
+    #[test]
+    fn into_mem_digest() {
+        let mut file = ::tempfile::tempfile().unwrap();
+        file.write_all(&bytes()).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        let from_bytes = large(Body::from(bytes()).into_mem(Some(99)).unwrap());
+        let from_reader = large(
+            Body::from_reader(Cursor::new(bytes()), None)
+                .into_mem(Some(99))
+                .unwrap(),
+        );
+        let from_file = large(Body::from(file).into_mem(Some(99)).unwrap());
+
+        assert_eq!(
+            from_bytes.sha256,
+            "bce0aff19cf5aa6a7469a30d61d04e4376e4bbf6381052ee9e7f33925c954d52"
+        );
+        assert_eq!(from_bytes.length, 100);
+        assert_eq!(from_bytes, from_reader);
+        assert_eq!(from_bytes, from_file);
+    }
+
+    #[test]
+    fn large_body_read_again() {
+        for body in [
+            Body::from(bytes()),
+            Body::from_reader(Cursor::new(bytes()), Some(100)),
+        ] {
+            let mem = body.into_mem(Some(10)).unwrap();
+            // Every copy reads the whole body.
+            for _ in 0..2 {
+                let read = Body::from(mem.clone()).try_to_vec().unwrap();
+                assert_eq!(read, bytes());
+            }
+        }
+    }
+}
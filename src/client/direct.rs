@@ -2,7 +2,7 @@ use client::{Client, Response};
 use config::ClientConfig;
 use error::Error;
 use request::Request;
-use std::io::Read;
+use response::StreamingResponse;
 
 /// Just a regular client performing no mocking at all.
 ///
@@ -20,8 +20,22 @@ impl DirectClient {
     }
 }
 
+impl Default for DirectClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Client for DirectClient {
     fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+        self.execute_streaming(config, request)?.into_response()
+    }
+
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+    ) -> Result<StreamingResponse, Error> {
         // Some information potentially useful for debugging.
         debug!(
             "DirectClient performing {} request of URL: {}",
@@ -31,38 +45,52 @@ impl Client for DirectClient {
         //trace!("request body: {:?}", request.header.body);
 
         // Use internal config if none was provided together with the request.
-        let config = config.unwrap_or_else(|| &self.config);
+        let config = config.unwrap_or(&self.config);
 
         // Setup the client instance.
         let mut client_builder = ::reqwest::blocking::Client::builder()
             .gzip(config.gzip)
             .redirect(config.redirect.clone().into())
-            .referer(config.referer);
-        if let Some(timeout) = config.timeout.clone() {
-            client_builder = client_builder.timeout(timeout);
+            .referer(config.referer)
+            .danger_accept_invalid_certs(config.danger_accept_invalid_certs);
+        // The timeout of a blocking client limits every wait for the connection, the one of
+        // a request the whole request. Passing none disables its default of 30 seconds.
+        client_builder = client_builder.timeout(config.read_timeout);
+        if let Some(timeout) = config.connect_timeout {
+            client_builder = client_builder.connect_timeout(timeout);
+        }
+        for cert in &config.root_certificates {
+            client_builder = client_builder.add_root_certificate(cert.clone());
+        }
+        if let Some(ref identity) = config.identity {
+            client_builder = client_builder.identity(identity.clone());
+        }
+        if let Some(version) = config.min_tls_version {
+            client_builder = client_builder.min_tls_version(version);
         }
         let client = client_builder.build()?;
 
         // Build the request.
-        let mut builder = client.request(request.header.method, request.header.url);
+        let mut builder = client
+            .request(request.header.method, request.header.url)
+            .headers(request.header.headers);
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
         if let Some(body) = request.body {
             builder = builder.body(::reqwest::blocking::Body::from(body));
         }
 
         // Send the request.
-        let mut response = builder.send()?;
+        let response = builder.send()?;
 
-        // Extract the response.
-        Ok(Response {
-            url: response.url().clone(),
-            status: response.status().clone(),
-            headers: response.headers().clone(),
-            body: {
-                let mut buf = Vec::<u8>::new();
-                response.read_to_end(&mut buf)?;
-                buf
-            },
-        })
+        // The body is read from the connection as the response is read.
+        Ok(StreamingResponse::new(
+            response.url().clone(),
+            response.status(),
+            response.headers().clone(),
+            response,
+        ))
     }
 
     fn config(&self) -> &ClientConfig {
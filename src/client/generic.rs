@@ -3,6 +3,7 @@ use client::{DirectClient, RecordingTarget, ReplayClient, StubClient};
 use config::ClientConfig;
 use error::Error;
 use request::Request;
+use response::StreamingResponse;
 use std::path::PathBuf;
 
 enum InnerClient {
@@ -95,6 +96,18 @@ impl Client for GenericClient {
         }
     }
 
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+    ) -> Result<StreamingResponse, Error> {
+        match self.inner {
+            InnerClient::Direct(ref client) => client.execute_streaming(config, request),
+            InnerClient::Replay(ref client) => client.execute_streaming(config, request),
+            InnerClient::Stub(ref client) => client.execute_streaming(config, request),
+        }
+    }
+
     fn config(&self) -> &ClientConfig {
         match self.inner {
             InnerClient::Direct(ref client) => client.config(),
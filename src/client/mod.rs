@@ -5,7 +5,7 @@ use error::Error;
 use request::Request;
 use request_builder::RequestBuilder;
 use reqwest::{IntoUrl, Method};
-use response::Response;
+use response::{Response, StreamingResponse};
 
 /// Provides a unified interface over the different Clients.
 ///
@@ -18,6 +18,18 @@ pub trait Client: Sized {
     /// provided config here.
     fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error>;
 
+    /// Execute a request, returning the response before its body was read.
+    ///
+    /// The default implementation reads the whole body through `execute`, clients able to
+    /// stream the body override it.
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+    ) -> Result<StreamingResponse, Error> {
+        self.execute(config, request).map(StreamingResponse::from)
+    }
+
     /// Returns a immutable reference to the internal config.
     fn config(&self) -> &ClientConfig;
 
@@ -67,12 +79,18 @@ mod direct;
 pub use self::direct::DirectClient;
 
 mod replay;
-pub use self::replay::{RecordingTarget, ReplayClient};
+pub use self::replay::{
+    Exhausted, FileNameRequest, FileNaming, RecordMode, RecordingTarget, ReplayClient,
+};
 
 mod stub;
 pub use self::stub::{
-    RequestStubber, ResponseStubber, StubClient, StubDefault, StubSettings, StubStrictness,
+    RegisterStubError, RequestStubber, ResponseStubber, StubClient, StubDefault, StubSettings,
+    StubStrictness,
 };
 
 mod generic;
 pub use self::generic::GenericClient;
+
+mod service;
+pub use self::service::{ClientService, ClientServiceFuture, ServiceClient};
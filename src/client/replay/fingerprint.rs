@@ -0,0 +1,239 @@
+//! The names of the replay files in a `RecordingTarget::Dir`.
+//!
+//! A request is named by the first 16 hex digits of the SHA-256 digest of its canonical
+//! encoding, which only depends on the request itself:
+//!
+//! ```text
+//! encoding = item("reqwest_mock request 1") item(method) item(url)
+//!            count(number of headers) { item(name) item(value) }
+//!            body
+//! body     = item("none") | item("sha256") item(hex digest) count(length)
+//! item(b)  = count(length of b) b
+//! count(n) = n as unsigned 64 bit big endian integer
+//! ```
+//!
+//! The method is its name, e. g. `GET`, and the url is serialized as by `Url::as_str`.
+//! Headers are sorted by their lowercase name, values of the same name stay in the order they
+//! were added. The body is identified by the lowercase hex encoded SHA-256 digest and length of
+//! its content, whether it was kept in memory or not.
+//!
+//! The names must never change, as that would orphan the existing recordings, the test
+//! vectors below make sure of that. If the encoding has to change, its version number in the
+//! first item has to be increased and the old names migrated.
+
+use body::BodyMem;
+use request::RequestMem;
+use sha2::{Digest, Sha256};
+use std::hash::{Hash, Hasher};
+use twox_hash::XxHash;
+
+/// The file name of the replay file recording `request`, without the extension.
+pub(super) fn file_stem(request: &RequestMem) -> String {
+    let digest = Sha256::digest(encode(request));
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The file stem used before `file_stem`, which depended on the `Hash` implementations of
+/// the dependencies and is only used to find old recordings.
+pub(super) fn legacy_file_stem(request: &RequestMem) -> String {
+    let mut hasher = XxHash::with_seed(42);
+    request.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+/// The canonical encoding of `request`.
+fn encode(request: &RequestMem) -> Vec<u8> {
+    let mut out = Vec::new();
+    item(&mut out, b"reqwest_mock request 1");
+    item(&mut out, request.header.method.as_str().as_bytes());
+    item(&mut out, request.header.url.as_str().as_bytes());
+
+    let mut headers: Vec<_> = request.header.headers.iter().collect();
+    // The sort is stable, keeping values of the same name in order.
+    headers.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+    count(&mut out, headers.len() as u64);
+    for (name, value) in headers {
+        item(&mut out, name.as_str().as_bytes());
+        item(&mut out, value.as_bytes());
+    }
+
+    match request.body {
+        None => item(&mut out, b"none"),
+        Some(BodyMem::Bytes(ref bytes)) => {
+            let digest: String = Sha256::digest(bytes)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            item(&mut out, b"sha256");
+            item(&mut out, digest.as_bytes());
+            count(&mut out, bytes.len() as u64);
+        }
+        Some(BodyMem::Large(ref large)) => {
+            item(&mut out, b"sha256");
+            item(&mut out, large.sha256.as_bytes());
+            count(&mut out, large.length);
+        }
+    }
+    out
+}
+
+fn item(out: &mut Vec<u8>, bytes: &[u8]) {
+    count(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn count(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&n.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body::Body;
+    use request::RequestHeader;
+    use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+    use reqwest::{Method, Url};
+
+    fn request(
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&str>,
+    ) -> RequestMem {
+        let mut map = HeaderMap::new();
+        for &(name, value) in headers {
+            map.append(
+                name.parse::<::reqwest::header::HeaderName>().unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        RequestMem {
+            header: RequestHeader {
+                url: Url::parse(url).unwrap(),
+                method,
+                headers: map,
+            },
+            body: body.map(|body| BodyMem::Bytes(body.as_bytes().to_vec())),
+        }
+    }
+
+    /// Changing any of these breaks every existing recording.
+    #[test]
+    fn vectors() {
+        let vectors = [
+            (
+                request(Method::GET, "http://example.com/", &[], None),
+                "433d9577195eef5e",
+            ),
+            (
+                request(Method::GET, "https://example.com/a?b=c&d=%20", &[], None),
+                "ec304dedb6affbac",
+            ),
+            (
+                request(
+                    Method::POST,
+                    "http://example.com/items",
+                    &[("content-type", "application/json"), ("accept", "*/*")],
+                    Some(r#"{"a":1}"#),
+                ),
+                "d4e70c99f8121cad",
+            ),
+            (
+                request(Method::PUT, "http://example.com/", &[], Some("")),
+                "ce13253b20109d14",
+            ),
+            (
+                request(
+                    Method::GET,
+                    "http://example.com/",
+                    &[("x-multi", "1"), ("x-multi", "2")],
+                    None,
+                ),
+                "d6096cf958c1082e",
+            ),
+        ];
+        for &(ref request, expected) in &vectors {
+            assert_eq!(file_stem(request), expected, "{:?}", request);
+        }
+    }
+
+    /// Computed with the code which named the replay files before `file_stem`, changing any
+    /// of these loses the old recordings.
+    #[test]
+    fn legacy_vectors() {
+        let vectors = [
+            (
+                request(Method::GET, "http://example.com/", &[], None),
+                "8ab80ed3dea0c0e8",
+            ),
+            (
+                request(
+                    Method::POST,
+                    "http://example.com/items",
+                    &[("content-type", "application/json"), ("accept", "*/*")],
+                    Some(r#"{"a":1}"#),
+                ),
+                "2f2f18386ed4e53c",
+            ),
+            (
+                request(Method::PUT, "http://example.com/", &[], Some("")),
+                "c9a2cd9432e52bbf",
+            ),
+            (
+                request(
+                    Method::GET,
+                    "http://example.com/",
+                    &[("x-multi", "1"), ("x-multi", "2")],
+                    None,
+                ),
+                "d820c62e8e215f5c",
+            ),
+        ];
+        for &(ref request, expected) in &vectors {
+            assert_eq!(legacy_file_stem(request), expected, "{:?}", request);
+        }
+    }
+
+    #[test]
+    fn canonical() {
+        // The order of headers with different names doesn't matter.
+        let a = request(
+            Method::GET,
+            "http://example.com/",
+            &[("accept", "*/*"), ("content-type", "text/plain")],
+            None,
+        );
+        let mut b = request(Method::GET, "http://example.com/", &[], None);
+        b.header
+            .headers
+            .insert(CONTENT_TYPE, "text/plain".parse().unwrap());
+        b.header.headers.insert(ACCEPT, "*/*".parse().unwrap());
+        assert_eq!(file_stem(&a), file_stem(&b));
+
+        // The order of values of the same name does.
+        let a = request(
+            Method::GET,
+            "http://example.com/",
+            &[("x-multi", "1"), ("x-multi", "2")],
+            None,
+        );
+        let b = request(
+            Method::GET,
+            "http://example.com/",
+            &[("x-multi", "2"), ("x-multi", "1")],
+            None,
+        );
+        assert_ne!(file_stem(&a), file_stem(&b));
+
+        // Bodies too large to keep in memory are named like the ones which are kept.
+        let mut large = request(Method::POST, "http://example.com/", &[], None);
+        large.body = Some(Body::from(b"abc".to_vec()).into_mem(Some(1)).unwrap());
+        let bytes = request(Method::POST, "http://example.com/", &[], Some("abc"));
+        assert_eq!(file_stem(&large), file_stem(&bytes));
+
+        // No body differs from an empty one.
+        let a = request(Method::POST, "http://example.com/", &[], None);
+        let b = request(Method::POST, "http://example.com/", &[], Some(""));
+        assert_ne!(file_stem(&a), file_stem(&b));
+    }
+}
@@ -0,0 +1,153 @@
+//! Migrations of replay files from older format versions to the current one.
+//!
+//! Every format change comes with a function converting the JSON of a file of the previous
+//! version, so recordings don't have to be made again when upgrading the crate.
+
+use super::FORMAT_VERSION;
+use error::{Error, ErrorKind};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// The oldest format version which can be migrated, files of older versions are too old.
+const OLDEST_MIGRATABLE: u64 = 3;
+
+/// Converts the content of a file of one version to the next version.
+type Migration = fn(Value) -> Result<Value, Error>;
+
+/// The migration of files of version `OLDEST_MIGRATABLE + i` to the next version is at
+/// index `i`.
+const MIGRATIONS: &[Migration] = &[v3_to_v4, v4_to_v5];
+
+/// The result of migrating the content of a replay file.
+pub(super) enum Migrated {
+    /// The file already had the current version.
+    Current(Value),
+
+    /// The file was migrated from the version.
+    From(u64, Value),
+
+    /// The file is too old to be migrated, it has the version or none at all.
+    TooOld(Option<u64>),
+}
+
+/// Migrate `value`, the content of the replay file `path`, to the current format version.
+///
+/// Files of a newer version than the current one are an error, as they can't be read and
+/// recording them again would lose the newer recording.
+pub(super) fn to_current(path: &Path, mut value: Value) -> Result<Migrated, Error> {
+    let current = u64::from(FORMAT_VERSION);
+    let found = match value {
+        Value::Object(ref obj) => obj.get("format_version").and_then(Value::as_u64),
+        _ => None,
+    };
+    let version = match found {
+        Some(version) if version > current => {
+            return Err(ErrorKind::ReplayFormatMismatch {
+                path: path.to_path_buf(),
+                found,
+                expected: current,
+            }
+            .into());
+        }
+        Some(version) if version == current => return Ok(Migrated::Current(value)),
+        Some(version) if version >= OLDEST_MIGRATABLE => version,
+        _ => return Ok(Migrated::TooOld(found)),
+    };
+
+    for step in version..current {
+        debug!(
+            "Migrating replay file {:?} from format version {}.",
+            path, step
+        );
+        value = MIGRATIONS[(step - OLDEST_MIGRATABLE) as usize](value)?;
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("format_version".to_string(), Value::from(step + 1));
+        }
+    }
+    Ok(Migrated::From(version, value))
+}
+
+/// Call `f` with the object of every exchange stored in `value` of version 4 or older, the first
+/// request and response and those following redirects.
+fn for_each_exchange<F>(value: &mut Value, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(&mut Map<String, Value>) -> Result<(), Error>,
+{
+    if let Some(data) = value.as_object_mut() {
+        f(data)?;
+        if let Some(redirects) = data.get_mut("redirects").and_then(Value::as_array_mut) {
+            for exchange in redirects.iter_mut().filter_map(Value::as_object_mut) {
+                f(exchange)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 3 stored request bodies as arrays of bytes and response bodies base64 encoded,
+/// version 4 stores both as text or JSON if possible.
+fn v3_to_v4(mut value: Value) -> Result<Value, Error> {
+    fn migrate_body(
+        exchange: &mut Map<String, Value>,
+        part: &str,
+        decode: fn(Value) -> Result<Vec<u8>, Error>,
+    ) -> Result<(), Error> {
+        let part = match exchange.get_mut(part).and_then(Value::as_object_mut) {
+            Some(part) => part,
+            None => return Ok(()),
+        };
+        match part.remove(::helper::F_BODY) {
+            None | Some(Value::Null) => {}
+            Some(body) => {
+                let (field, value) = ::helper::store_body(&decode(body)?);
+                part.insert(field.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+    fn request_body(body: Value) -> Result<Vec<u8>, Error> {
+        Ok(::serde_json::from_value(body)?)
+    }
+    fn response_body(body: Value) -> Result<Vec<u8>, Error> {
+        let encoded: String = ::serde_json::from_value(body)?;
+        ::base64::decode(&encoded).map_err(|e| Error::with_source(ErrorKind::Decode, e))
+    }
+
+    for_each_exchange(&mut value, |exchange| {
+        migrate_body(exchange, "request", request_body)?;
+        migrate_body(exchange, "response", response_body)
+    })?;
+    Ok(value)
+}
+
+/// Version 4 stored a single response to the request, version 5 stores the responses to
+/// repeated requests in order. The body files keep their names, which are those of the first
+/// response.
+fn v4_to_v5(mut value: Value) -> Result<Value, Error> {
+    if let Some(data) = value.as_object_mut() {
+        let mut recording = Map::new();
+        for field in &["response", "body_file", "redirects"] {
+            if let Some(v) = data.remove(*field) {
+                recording.insert(field.to_string(), v);
+            }
+        }
+        data.insert(
+            "responses".to_string(),
+            Value::Array(vec![Value::Object(recording)]),
+        );
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_per_version() {
+        assert_eq!(
+            MIGRATIONS.len() as u64,
+            u64::from(FORMAT_VERSION) - OLDEST_MIGRATABLE
+        );
+    }
+}
@@ -0,0 +1,1057 @@
+use client::{Client, DirectClient};
+use config::{ClientConfig, RedirectPolicy};
+use diff::RequestDiff;
+use error::{Error, ErrorKind};
+use request::{Request, RequestMem};
+use reqwest::Url;
+use response::{Response, StreamingResponse};
+use url::Position;
+
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{create_dir_all, remove_file, rename, File};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+mod fingerprint;
+
+mod migrate;
+use self::migrate::Migrated;
+
+mod naming;
+pub use self::naming::{FileNameRequest, FileNaming};
+
+mod storage;
+use self::storage::{DirLock, Index, IndexEntry, PendingFile};
+
+/// The version of the storage format. Files of older versions are migrated when they are read,
+/// see `migrate`.
+///
+/// Version 4 stores bodies as text or embedded JSON if possible, see `helper::store_body`.
+/// Version 5 stores the responses to repeated requests in order.
+const FORMAT_VERSION: u8 = 5;
+
+/// The recording target.
+pub enum RecordingTarget {
+    /// A single file is used for recording one request, if the request changes the file is
+    /// replaced by a new one.
+    File(PathBuf),
+
+    /// A directory is used in which multiple replay files are managed for each request data
+    /// an individual file is created.
+    ///
+    /// The files are named by a digest of the request, which is stable across versions of
+    /// this crate and its dependencies, see `ReplayClient::file_naming` for other names. An
+    /// `index.json` file in the directory lists the method and URL of every file.
+    Dir(PathBuf),
+}
+
+impl RecordingTarget {
+    /// Shorthand to specify `RecordingTarget::File`.
+    pub fn file<P: Into<PathBuf>>(file: P) -> Self {
+        RecordingTarget::File(file.into())
+    }
+
+    /// Shorthand to specify `RecordingTarget::Dir`.
+    pub fn dir<P: Into<PathBuf>>(dir: P) -> Self {
+        RecordingTarget::Dir(dir.into())
+    }
+
+    /// Upgrade the replay file, or every replay file in the directory and its
+    /// subdirectories, to the current format version in place. Returns the number of files
+    /// which were upgraded.
+    ///
+    /// Files directly in a directory are also renamed to the name `FileNaming::Hash` gives
+    /// them if they were recorded before the current naming of files, together with their
+    /// body files. If a file with that name exists already, the old recording is removed
+    /// instead. The index of the directory is built again.
+    ///
+    /// Replaying migrates files of older versions as well, but only in memory. Upgrading
+    /// them once keeps the migration out of every test run, and lets the recordings be
+    /// reviewed in the current format.
+    ///
+    /// Fails with `ReplayFormatMismatch` on the first file which is too old to be migrated
+    /// or of a newer version than this version of the crate supports, files before it are
+    /// upgraded already.
+    pub fn upgrade(&self) -> Result<usize, Error> {
+        let lock_dir = self.root_dir();
+        if !lock_dir.exists() {
+            return Ok(0);
+        }
+        let _lock = DirLock::exclusive(&lock_dir)?;
+        let files = self.replay_files()?;
+
+        let mut upgraded = 0;
+        let mut index = Index::new();
+        for file in files {
+            let (changed, kept) = upgrade_file(&file, self)?;
+            if changed {
+                upgraded += 1;
+            }
+            if let Some((path, entry)) = kept {
+                index.insert(storage::index_key(&lock_dir, &path), entry);
+            }
+        }
+        if let RecordingTarget::Dir(ref dir) = *self {
+            storage::write_index(dir, &index)?;
+        }
+        Ok(upgraded)
+    }
+
+    /// The replay files which were not used since usage was last reset, by clients with
+    /// `ReplayClient::track_usage` enabled in any process.
+    ///
+    /// Fails if no usage was recorded at all, as every file would be unused.
+    pub fn unused(&self) -> Result<Vec<PathBuf>, Error> {
+        let root = self.root_dir();
+        if !root.exists() {
+            return Ok(Vec::new());
+        }
+        let _lock = DirLock::shared(&root)?;
+        self.unused_files(&root)
+    }
+
+    /// Delete the replay files which were not used since usage was last reset, together with
+    /// their body files, and reset the usage. Returns the deleted replay files.
+    ///
+    /// Only prune after a complete run of the tests with `ReplayClient::track_usage` enabled,
+    /// otherwise the recordings of the tests which did not run are deleted as well. With
+    /// `dry_run` nothing is deleted or reset, only the files which would be deleted are
+    /// returned.
+    ///
+    /// Fails if no usage was recorded at all, as every file would be deleted.
+    pub fn prune(&self, dry_run: bool) -> Result<Vec<PathBuf>, Error> {
+        let root = self.root_dir();
+        if !root.exists() {
+            return Ok(Vec::new());
+        }
+        let _lock = DirLock::exclusive(&root)?;
+        let unused = self.unused_files(&root)?;
+        if dry_run {
+            return Ok(unused);
+        }
+
+        for file in &unused {
+            info!("reqwest_mock: Deleting unused replay file {:?}.", file);
+            for body_file in storage::body_files(file)? {
+                remove_file(body_file)?;
+            }
+            remove_file(file)?;
+        }
+        if let RecordingTarget::Dir(ref dir) = *self {
+            let mut index = storage::read_index(dir);
+            for file in &unused {
+                index.remove(&storage::index_key(dir, file));
+            }
+            storage::write_index(dir, &index)?;
+        }
+        storage::reset_usage(&root)?;
+        Ok(unused)
+    }
+
+    /// Forget which replay files were used, e. g. before a complete run of the tests which
+    /// is followed by `prune`.
+    pub fn reset_usage(&self) -> Result<(), Error> {
+        let root = self.root_dir();
+        if !root.exists() {
+            return Ok(());
+        }
+        let _lock = DirLock::exclusive(&root)?;
+        storage::reset_usage(&root)
+    }
+
+    /// The directory which is locked when accessing the replay files, and contains the index
+    /// and usage files.
+    fn root_dir(&self) -> PathBuf {
+        match *self {
+            RecordingTarget::File(ref file) => storage::dir_of(file),
+            RecordingTarget::Dir(ref dir) => dir.clone(),
+        }
+    }
+
+    /// The existing replay files.
+    fn replay_files(&self) -> Result<Vec<PathBuf>, Error> {
+        match *self {
+            RecordingTarget::File(ref file) if file.exists() => Ok(vec![file.clone()]),
+            RecordingTarget::File(_) => Ok(Vec::new()),
+            RecordingTarget::Dir(ref dir) => storage::replay_files(dir),
+        }
+    }
+
+    /// The replay files not used according to the usage file in `root`, which must be locked.
+    fn unused_files(&self, root: &Path) -> Result<Vec<PathBuf>, Error> {
+        let used = storage::read_usage(root)?
+            .ok_or_else(|| ErrorKind::NoUsageRecorded(root.to_path_buf()))?;
+        Ok(self
+            .replay_files()?
+            .into_iter()
+            .filter(|file| !used.contains(&storage::index_key(root, file)))
+            .collect())
+    }
+}
+
+/// Whether `ReplayClient` may perform and record requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordMode {
+    /// Record requests which were not recorded yet or changed, and replay files too old to be
+    /// migrated to the current format version. How a changed request differs is logged.
+    /// This is the default.
+    #[default]
+    Record,
+
+    /// Never perform requests, returning a `ReplayMissing`, `ReplayChanged` or
+    /// `ReplayFormatMismatch` error instead. Useful on CI, where a missing or outdated
+    /// recording is a mistake.
+    ///
+    /// `force_record_next` still records the next request.
+    ReplayOnly,
+}
+
+/// What `ReplayClient` does when a request is made more often than its responses were
+/// recorded.
+///
+/// Use `Record` to record the responses to a request made repeatedly, e. g. when polling a
+/// job, as the default replays the first response recorded to every following request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Exhausted {
+    /// Replay the last response again. This is the default, so a request recorded once can
+    /// be made any number of times.
+    #[default]
+    RepeatLast,
+
+    /// Fail with a `ReplayExhausted` error.
+    Error,
+
+    /// Perform the request and add the response to the recording. Fails with a
+    /// `ReplayExhausted` error instead if the `RecordMode` is `ReplayOnly`.
+    Record,
+}
+
+/// Records responses to requests and replays them if the request is unchanged.
+///
+/// If the same request is made again, the responses are replayed in the order they were
+/// recorded, see `Exhausted` for what happens after the last one. Every client starts with
+/// the first response.
+///
+/// Redirects are recorded hop by hop, and replayed according to the `RedirectPolicy` of
+/// the config in use when replaying.
+///
+/// Response bodies longer than `ClientConfig::max_buffered_body` are stored in separate
+/// `.body` files next to the replay file, from which they are streamed when replaying.
+///
+/// Files are replaced atomically, and access to them is coordinated by an advisory lock on a
+/// `.reqwest_mock.lock` file in their directory, so tests running in parallel threads or
+/// processes can share recordings.
+///
+/// Requests are recorded through a `DirectClient` by default, see `with_client` for recording
+/// through another client.
+pub struct ReplayClient<C: Client = DirectClient> {
+    client: C,
+    config: ClientConfig,
+    target: RecordingTarget,
+    record_mode: RecordMode,
+    naming: FileNaming,
+    subdir: Option<PathBuf>,
+    track_usage: bool,
+    exhausted: Exhausted,
+    /// Locked after the directory of the replay files, when both are locked.
+    used: Mutex<BTreeSet<PathBuf>>,
+    positions: Mutex<HashMap<PathBuf, usize>>,
+    force_record_next: AtomicBool,
+}
+
+/// Where `ReplayClient::store_recording` puts a recording in the replay file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Store {
+    /// Replace the file by one with only this recording.
+    New,
+    /// Add it after the recordings in the file.
+    Append,
+    /// Replace the recording at the position, which could not be replayed.
+    Replace(usize),
+}
+
+impl ReplayClient {
+    /// Create a new `ReplayClient` instance reading and writing to the specified target.
+    pub fn new(target: RecordingTarget) -> Self {
+        Self::with_client(target, DirectClient::new())
+    }
+}
+
+impl<C: Client> ReplayClient<C> {
+    /// Create a new `ReplayClient` instance reading and writing to the specified target, and
+    /// recording the requests performed by `client`, e. g. a client sending them through a
+    /// proxy, or a `StubClient`.
+    ///
+    /// The `ReplayClient` starts with the config of `client`. Requests are recorded by
+    /// passing them to `client` together with the config of the `ReplayClient`, but with
+    /// redirects disabled, as every hop of a redirect chain is recorded separately.
+    pub fn with_client(target: RecordingTarget, client: C) -> Self {
+        ReplayClient {
+            config: client.config().clone(),
+            client,
+            target,
+            record_mode: RecordMode::default(),
+            naming: FileNaming::default(),
+            subdir: None,
+            track_usage: false,
+            exhausted: Exhausted::default(),
+            used: Mutex::new(BTreeSet::new()),
+            positions: Mutex::new(HashMap::new()),
+            force_record_next: AtomicBool::new(false),
+        }
+    }
+
+    /// Set whether requests may be performed and recorded.
+    pub fn record_mode(mut self, mode: RecordMode) -> Self {
+        self.record_mode = mode;
+        self
+    }
+
+    /// Set how the replay files in a `RecordingTarget::Dir` are named.
+    ///
+    /// Recordings with the names of `FileNaming::Hash` are still replayed after switching to
+    /// another naming, until they are recorded again.
+    pub fn file_naming(mut self, naming: FileNaming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Keep the replay files in a subdirectory of a `RecordingTarget::Dir`, e. g. one per
+    /// test. The index of the directory lists the files of all subdirectories.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reqwest_mock::{RecordingTarget, ReplayClient};
+    ///
+    /// // Tests run in threads named after them.
+    /// let test = std::thread::current().name().unwrap_or("main").replace("::", "/");
+    /// let client = ReplayClient::new(RecordingTarget::dir("tests/replays")).subdir(test);
+    /// ```
+    pub fn subdir<P: Into<PathBuf>>(mut self, subdir: P) -> Self {
+        self.subdir = Some(subdir.into());
+        self
+    }
+
+    /// Set whether the replay files used are recorded in a usage file next to them, so
+    /// `RecordingTarget::prune` can delete the ones no test uses anymore. Clients in all
+    /// processes add to the same file.
+    ///
+    /// This is disabled by default, as the usage file changes whenever the tests run. It can
+    /// be enabled for a single run, e. g. depending on an environment variable.
+    pub fn track_usage(mut self, track: bool) -> Self {
+        self.track_usage = track;
+        self
+    }
+
+    /// Set what happens when a request is made more often than its responses were recorded.
+    pub fn when_exhausted(mut self, exhausted: Exhausted) -> Self {
+        self.exhausted = exhausted;
+        self
+    }
+
+    /// The replay files this client replayed or recorded.
+    pub fn used_recordings(&self) -> Vec<PathBuf> {
+        self.used.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// The replay files of the target which neither this client nor, if usage is tracked, any
+    /// other client used since usage was last reset.
+    pub fn unused_recordings(&self) -> Result<Vec<PathBuf>, Error> {
+        let root = self.target.root_dir();
+        let _lock = match DirLock::shared(&root)? {
+            Some(lock) => lock,
+            None => return Ok(Vec::new()),
+        };
+        // Lock `used` after the directory, in the order `store_recording` locks them.
+        let used = self.used.lock().unwrap();
+        let tracked = storage::read_usage(&root)?.unwrap_or_default();
+        Ok(self
+            .target
+            .replay_files()?
+            .into_iter()
+            .filter(|file| {
+                !used.contains(file) && !tracked.contains(&storage::index_key(&root, file))
+            })
+            .collect())
+    }
+
+    /// Calling this method ensures that whatever next request is performed it will be recorded
+    /// again, even the exact same request was already made before.
+    ///
+    /// All responses recorded for the request before are discarded.
+    pub fn force_record_next(&self) {
+        self.force_record_next.store(true, Ordering::SeqCst);
+    }
+
+    fn replay_file_path(&self, request: &RequestMem) -> Result<PathBuf, Error> {
+        match self.target {
+            RecordingTarget::File(ref file) => Ok(file.clone()),
+            RecordingTarget::Dir(ref dir) => {
+                let mut path = dir.clone();
+                if let Some(ref subdir) = self.subdir {
+                    naming::check_relative(subdir)?;
+                    path.push(subdir);
+                }
+                let name = FileNameRequest::new(
+                    &request.header.method,
+                    &request.header.url,
+                    fingerprint::file_stem(request),
+                );
+                path.push(self.naming.relative_path(&name)?);
+                if path == dir.join(storage::INDEX_FILE) {
+                    return Err(ErrorKind::InvalidReplayPath(path).into());
+                }
+                Ok(path)
+            }
+        }
+    }
+
+    /// The path of the file to read the recording of `request` from. Recordings made
+    /// with `FileNaming::Hash`, or before the file names were stable, are read from their
+    /// old file until they are recorded again or `RecordingTarget::upgrade` renames them.
+    fn existing_file_path(&self, request: &RequestMem) -> Result<PathBuf, Error> {
+        let file = self.replay_file_path(request)?;
+        if let RecordingTarget::Dir(ref dir) = self.target {
+            let previous = [
+                fingerprint::file_stem(request),
+                fingerprint::legacy_file_stem(request),
+            ];
+            for stem in &previous {
+                let previous = dir.join(format!("{}.json", stem));
+                if !file.exists() && previous.exists() {
+                    return Ok(previous);
+                }
+            }
+        }
+        Ok(file)
+    }
+
+    /// The recording in the directory of the target which differs the least from `request`,
+    /// among the ones of requests with its method and URL up to the path, and how it
+    /// differs. They are found through the index, the replay files must be locked.
+    fn closest_recording(
+        &self,
+        request: &RequestMem,
+    ) -> Result<Option<(PathBuf, RequestDiff)>, Error> {
+        let dir = match self.target {
+            RecordingTarget::Dir(ref dir) => dir,
+            RecordingTarget::File(_) => return Ok(None),
+        };
+        let base = &request.header.url[..Position::AfterPath];
+        let mut closest: Option<(PathBuf, RequestDiff)> = None;
+        for (key, entry) in storage::read_index(dir) {
+            let same_base = Url::parse(&entry.url)
+                .map(|url| url[..Position::AfterPath] == *base)
+                .unwrap_or(false);
+            if entry.method != request.header.method.as_str() || !same_base {
+                continue;
+            }
+            let file = dir.join(&key);
+            // Files which can't be read are recorded again when their request is made.
+            let data = match self.get_data(&file) {
+                Ok(Some(data)) => data,
+                _ => continue,
+            };
+            let diff = RequestDiff::between(&data.request, request);
+            let closer = closest
+                .as_ref()
+                .map_or(true, |c| diff.fields().len() < c.1.fields().len());
+            if !diff.is_empty() && closer {
+                closest = Some((file, diff));
+            }
+        }
+        Ok(closest)
+    }
+
+    /// Remember that the replay file `file` was used, and record it in the usage file if
+    /// usage is tracked. The replay files must be locked.
+    fn mark_used(&self, file: &Path) -> Result<(), Error> {
+        let newly_used = self.used.lock().unwrap().insert(file.to_path_buf());
+        if newly_used && self.track_usage {
+            let root = self.target.root_dir();
+            storage::append_usage(&root, &storage::index_key(&root, file))?;
+        }
+        Ok(())
+    }
+
+    /// The position of the response to the next request in the responses recorded to
+    /// `file`, as far as this client is concerned.
+    fn next_position(&self, file: &Path) -> usize {
+        self.positions
+            .lock()
+            .unwrap()
+            .get(file)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    fn set_next_position(&self, file: &Path, position: usize) {
+        self.positions
+            .lock()
+            .unwrap()
+            .insert(file.to_path_buf(), position);
+    }
+
+    /// The possible results:
+    ///
+    /// Err(_)      → something went wrong.
+    /// Ok(None)    → no data was stored yet, i. e. the file doesn't exist yet.
+    /// Ok(Some(_)) → the actual data
+    fn get_data(&self, file: &Path) -> Result<Option<ReplayData>, Error> {
+        debug!("Checking presence of replay file: {:?}", file);
+
+        if !file.exists() {
+            debug!("Existing replay file was found.");
+            Ok(None)
+        } else {
+            debug!("Reading existing replay file.");
+            let f = File::open(file)?;
+            let value: Value = ::serde_json::from_reader(f)?;
+
+            match migrate::to_current(file, value)? {
+                Migrated::Current(value) | Migrated::From(_, value) => {
+                    Ok(::serde_json::from_value(value)?)
+                }
+                Migrated::TooOld(found) if self.record_mode == RecordMode::ReplayOnly => {
+                    Err(ErrorKind::ReplayFormatMismatch {
+                        path: file.to_path_buf(),
+                        found,
+                        expected: u64::from(FORMAT_VERSION),
+                    }
+                    .into())
+                }
+                Migrated::TooOld(found) => {
+                    warn!(
+                        "reqwest_mock: Replay file {:?} has format version {:?}, which is too \
+                         old to be migrated, recording again now.",
+                        file, found
+                    );
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// Store `recording` of the responses to `request`, and the files with their bodies by
+    /// the hop of the chain they belong to. Returns the position of the recording in the file.
+    ///
+    /// Unless a new file is stored, the recording is added to the responses in `source`, the
+    /// file they were replayed from. It is moved to the current name of the file if it has
+    /// another one.
+    fn store_recording(
+        &self,
+        source: &Path,
+        request: RequestMem,
+        mut recording: Recording,
+        body_files: Vec<(usize, PendingFile)>,
+        store: Store,
+    ) -> Result<usize, Error> {
+        let file = self.replay_file_path(&request)?;
+        debug!("Writing replay file at: {:?}", file);
+
+        let _lock = DirLock::exclusive(&self.target.root_dir())?;
+        // Read the file again, another client might have changed it in the meantime.
+        let previous = match store {
+            Store::New => None,
+            Store::Append | Store::Replace(_) => self.get_data(source)?,
+        };
+        let mut data = match previous {
+            Some(data) if data.request == request => data,
+            _ => ReplayData {
+                request,
+                responses: Vec::new(),
+                format_version: FORMAT_VERSION,
+            },
+        };
+        let moved = source != file && !data.responses.is_empty();
+        if moved {
+            debug!("Moving replay file {:?} to {:?}.", source, file);
+            move_body_files(source, &file, &mut data)?;
+        }
+        let position = match store {
+            Store::Replace(position) if position < data.responses.len() => position,
+            _ => data.responses.len(),
+        };
+
+        {
+            let mut names = recording.body_files_mut();
+            for (hop, mut body_file) in body_files {
+                body_file.set_path(body_file_path(&file, position, hop));
+                *names[hop] = body_file.file_name();
+                body_file.persist()?;
+            }
+        }
+        if position < data.responses.len() {
+            data.responses[position] = recording;
+        } else {
+            data.responses.push(recording);
+        }
+        write_data(&file, &data)?;
+        remove_unused_body_files(&file, &data)?;
+        if moved {
+            for body_file in storage::body_files(source)? {
+                remove_file(body_file)?;
+            }
+            remove_file(source)?;
+        }
+        self.mark_used(&file)?;
+
+        if let RecordingTarget::Dir(ref dir) = self.target {
+            let mut index = storage::read_index(dir);
+            if moved {
+                index.remove(&storage::index_key(dir, source));
+            }
+            index.insert(storage::index_key(dir, &file), data.index_entry());
+            storage::write_index(dir, &index)?;
+        }
+        Ok(position)
+    }
+}
+
+impl<C: Client> Client for ReplayClient<C> {
+    fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+        self.execute_streaming(config, request)?.into_response()
+    }
+
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+    ) -> Result<StreamingResponse, Error> {
+        // Use internal config if none was provided together with the request.
+        let config = config.unwrap_or(&self.config);
+        let req: RequestMem = request.into_mem(config.max_buffered_body)?;
+
+        // Some information potentially useful for debugging.
+        debug!(
+            "ReplayClient performing {} request of URL: {}",
+            req.header.method, req.header.url
+        );
+        trace!("request headers: {:?}", req.header.headers);
+        trace!("request body: {:?}", req.body);
+
+        // Check if the request was already performed with this exact arguments,
+        // if it was just return the existing result otherwise perform the request and store
+        // the output.
+
+        let path = self.replay_file_path(&req)?;
+        let file = self.existing_file_path(&req)?;
+        let force_record = self.force_record_next.swap(false, Ordering::SeqCst);
+        let next = self.next_position(&path);
+        // Keep the recording from being replaced while it is replayed.
+        let lock = DirLock::shared(&self.target.root_dir())?;
+        let data = if force_record {
+            debug!("Force record was requested, not checking the replay file.");
+            None
+        } else {
+            self.get_data(&file)?
+        };
+        let mut store = Store::New;
+        if let Some(d) = data {
+            if d.request != req {
+                let diff = RequestDiff::between(&d.request, &req);
+                if self.record_mode == RecordMode::ReplayOnly {
+                    return Err(ErrorKind::ReplayChanged { path: file, diff }.into());
+                }
+                info!(
+                    "reqwest_mock: Request has changed, recording again now.\n{}",
+                    diff
+                );
+            } else if !d.responses.is_empty() {
+                let recorded = d.responses.len();
+                let position = if next < recorded {
+                    Some(next)
+                } else {
+                    match self.exhausted {
+                        Exhausted::RepeatLast => Some(recorded - 1),
+                        Exhausted::Record if self.record_mode == RecordMode::Record => None,
+                        Exhausted::Error | Exhausted::Record => {
+                            return Err(ErrorKind::ReplayExhausted {
+                                path: file,
+                                recorded,
+                            }
+                            .into());
+                        }
+                    }
+                };
+
+                match position {
+                    Some(position) => {
+                        // Replay the recorded redirects according to the current policy, which
+                        // might want to follow one that was not followed when recording.
+                        let recording = &d.responses[position];
+                        let mut complete = true;
+                        let result = ::redirect::follow(config, req.clone(), |hop| {
+                            let replayed = recording.response_to(&d.request, hop).map(
+                                |(response, body_file)| replay_response(&file, response, body_file),
+                            );
+                            match replayed {
+                                // Only a missing body file means it was not recorded.
+                                Some(Err(ref e)) if e.kind() == io::ErrorKind::NotFound => {}
+                                Some(result) => return result.map_err(Error::from),
+                                None => {}
+                            }
+                            complete = false;
+                            Err(ErrorKind::ReplayMissing(file.clone()).into())
+                        });
+                        if complete {
+                            self.mark_used(&file)?;
+                            self.set_next_position(&path, position + 1);
+                            return result;
+                        }
+                        info!(
+                            "reqwest_mock: Redirect or response body was not recorded, \
+                             recording again now."
+                        );
+                        store = Store::Replace(position);
+                    }
+                    None => {
+                        info!(
+                            "reqwest_mock: All {} responses recorded in {:?} were replayed, \
+                             recording another one now.",
+                            recorded, file
+                        );
+                        store = Store::Append;
+                    }
+                }
+            }
+        } else if !force_record {
+            // A changed request is named differently, so look for the recording it changed from.
+            if let Some((changed, diff)) = self.closest_recording(&req)? {
+                if self.record_mode == RecordMode::ReplayOnly {
+                    return Err(ErrorKind::ReplayChanged {
+                        path: changed,
+                        diff,
+                    }
+                    .into());
+                }
+                info!(
+                    "reqwest_mock: Request differs from the one recorded in {:?}, recording it \
+                     as a new request now.\n{}",
+                    changed, diff
+                );
+            }
+        }
+
+        drop(lock);
+        if self.record_mode == RecordMode::ReplayOnly && !force_record {
+            return Err(ErrorKind::ReplayMissing(path).into());
+        }
+
+        // We actually have to perform the request and store the response. Redirects are
+        // followed here instead of inside of reqwest, so every hop of the chain is recorded.
+        let hop_config = ClientConfig {
+            redirect: RedirectPolicy::None,
+            ..config.clone()
+        };
+        let mut exchanges = Vec::new();
+        let mut body_files = Vec::new();
+        let result = ::redirect::follow(config, req, |hop| {
+            let response = self
+                .client
+                .execute_streaming(Some(&hop_config), hop.clone().into())?;
+            // The position of the recording is only known when storing it.
+            let body_path = body_file_path(&path, 0, exchanges.len());
+            let (recorded, pending) =
+                record_response(response, config.max_buffered_body, &body_path)?;
+            let body: Box<dyn io::Read + Send> = match pending {
+                Some(ref pending) => Box::new(pending.reopen()?),
+                None => Box::new(Cursor::new(recorded.body.clone())),
+            };
+            let response = streaming_response(&recorded, body);
+            body_files.extend(pending.map(|pending| (exchanges.len(), pending)));
+            exchanges.push(RecordedExchange {
+                request: hop.clone(),
+                response: recorded,
+                body_file: None,
+            });
+            Ok(response)
+        });
+
+        // Also store the chain if the redirect policy failed it, so it fails again in replay.
+        if !exchanges.is_empty() {
+            let first = exchanges.remove(0);
+            let recording = Recording {
+                response: first.response,
+                body_file: None,
+                redirects: exchanges,
+            };
+            let position =
+                self.store_recording(&file, first.request, recording, body_files, store)?;
+            self.set_next_position(&path, position + 1);
+        }
+
+        // Return the response.
+        result
+    }
+
+    fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        &mut self.config
+    }
+}
+
+/// The path of the file storing the body of the response to the `hop`th request of the
+/// chain, in the recording at `position` in `file`.
+fn body_file_path(file: &Path, position: usize, hop: usize) -> PathBuf {
+    let stem = file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if position == 0 {
+        file.with_file_name(format!("{}.{}.body", stem, hop))
+    } else {
+        file.with_file_name(format!("{}.{}-{}.body", stem, position, hop))
+    }
+}
+
+/// Replace `file` by `data` atomically, the directory must be locked exclusively.
+fn write_data(file: &Path, data: &ReplayData) -> Result<(), Error> {
+    let mut pending = PendingFile::create(file)?;
+    ::serde_json::to_writer_pretty(pending.file(), data)?;
+    pending.persist()
+}
+
+/// Rename the body files of `data`, which was read from the replay file `from`, to their names
+/// next to the replay file `to`. The directories must be locked exclusively.
+fn move_body_files(from: &Path, to: &Path, data: &mut ReplayData) -> Result<(), Error> {
+    create_dir_all(storage::dir_of(to))?;
+    for (position, recording) in data.responses.iter_mut().enumerate() {
+        for (hop, body_file) in recording.body_files_mut().into_iter().enumerate() {
+            let name = match *body_file {
+                Some(ref name) => from.with_file_name(name),
+                None => continue,
+            };
+            if name.exists() {
+                let body_path = body_file_path(to, position, hop);
+                rename(name, &body_path)?;
+                *body_file = body_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove the body files of `file` which `data` doesn't refer to anymore, e. g. after recording
+/// a chain with fewer redirects again. The directory must be locked exclusively.
+fn remove_unused_body_files(file: &Path, data: &ReplayData) -> Result<(), Error> {
+    let used: BTreeSet<PathBuf> = data
+        .responses
+        .iter()
+        .flat_map(Recording::body_files)
+        .map(|name| file.with_file_name(name))
+        .collect();
+    for body_file in storage::body_files(file)? {
+        if !used.contains(&body_file) {
+            remove_file(body_file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Migrate the replay file `file` to the current format version, and move it to its current
+/// name in `target`, which must be locked exclusively.
+///
+/// Returns whether it was changed, and its path and index entry unless it was removed.
+fn upgrade_file(
+    file: &Path,
+    target: &RecordingTarget,
+) -> Result<(bool, Option<(PathBuf, IndexEntry)>), Error> {
+    let value: Value = ::serde_json::from_reader(File::open(file)?)?;
+    // Check that the migrated file can be read before replacing the old one.
+    let (mut data, mut changed): (ReplayData, bool) = match migrate::to_current(file, value)? {
+        Migrated::Current(value) => (::serde_json::from_value(value)?, false),
+        Migrated::From(version, value) => {
+            debug!(
+                "Upgrading replay file {:?} from format version {}.",
+                file, version
+            );
+            (::serde_json::from_value(value)?, true)
+        }
+        Migrated::TooOld(found) => {
+            return Err(ErrorKind::ReplayFormatMismatch {
+                path: file.to_path_buf(),
+                found,
+                expected: u64::from(FORMAT_VERSION),
+            }
+            .into())
+        }
+    };
+
+    // Files named before the names were stable are renamed, other names were chosen by the
+    // `FileNaming` of the client.
+    let legacy_name = format!("{}.json", fingerprint::legacy_file_stem(&data.request));
+    let path = match *target {
+        RecordingTarget::Dir(ref dir)
+            if storage::dir_of(file) == *dir && file.ends_with(&legacy_name) =>
+        {
+            dir.join(format!("{}.json", fingerprint::file_stem(&data.request)))
+        }
+        _ => file.to_path_buf(),
+    };
+    if path != file {
+        if path.exists() {
+            info!(
+                "reqwest_mock: Removing replay file {:?}, which was recorded again as {:?}.",
+                file, path
+            );
+            for body_file in storage::body_files(file)? {
+                remove_file(body_file)?;
+            }
+            remove_file(file)?;
+            return Ok((true, None));
+        }
+
+        debug!("Renaming replay file {:?} to {:?}.", file, path);
+        move_body_files(file, &path, &mut data)?;
+        changed = true;
+    }
+
+    if changed {
+        write_data(&path, &data)?;
+        if path != file {
+            remove_file(file)?;
+        }
+    }
+    let entry = data.index_entry();
+    Ok((changed, Some((path, entry))))
+}
+
+/// Read the body of `response` for storing it, writing it to a file replacing `body_path`
+/// instead of keeping it in memory if it is longer than `limit`.
+///
+/// Returns the response to store, and the body file if one was written. It still has to be
+/// persisted along with the replay file.
+fn record_response(
+    mut response: StreamingResponse,
+    limit: Option<u64>,
+    body_path: &Path,
+) -> Result<(Response, Option<PendingFile>), Error> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok((response.into_response()?, None)),
+    };
+    let head = ::body::read_head(&mut response, limit)?;
+    if head.len() as u64 <= limit {
+        let mut response = response.into_response()?;
+        response.body = head;
+        return Ok((response, None));
+    }
+
+    let mut pending = PendingFile::create(body_path)?;
+    pending.file().write_all(&head)?;
+    io::copy(&mut response, pending.file())?;
+
+    let mut response = response.into_response()?;
+    response.body = Vec::new();
+    Ok((response, Some(pending)))
+}
+
+/// Return a recorded response, streaming its body from the body file if there is one.
+fn replay_response(
+    file: &Path,
+    response: &Response,
+    body_file: Option<&String>,
+) -> io::Result<StreamingResponse> {
+    let body: Box<dyn io::Read + Send> = match body_file {
+        Some(name) => Box::new(File::open(file.with_file_name(name))?),
+        None => Box::new(Cursor::new(response.body.clone())),
+    };
+    Ok(streaming_response(response, body))
+}
+
+fn streaming_response(response: &Response, body: Box<dyn io::Read + Send>) -> StreamingResponse {
+    StreamingResponse::new(
+        response.url.clone(),
+        response.status,
+        response.headers.clone(),
+        body,
+    )
+}
+
+/// The data stored inside of a replay file.
+#[derive(Debug, Serialize, Deserialize)]
+struct ReplayData {
+    request: RequestMem,
+    /// The responses to `request`, in the order the request was made.
+    responses: Vec<Recording>,
+    format_version: u8,
+}
+
+impl ReplayData {
+    fn index_entry(&self) -> IndexEntry {
+        IndexEntry {
+            hash: fingerprint::file_stem(&self.request),
+            method: self.request.header.method.to_string(),
+            url: self.request.header.url.to_string(),
+        }
+    }
+}
+
+/// The response to one request, and the redirects followed from it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Recording {
+    /// The response to the request, which might be a redirect.
+    response: Response,
+    /// The name of the file storing the body of `response`, if it is not stored inline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_file: Option<String>,
+    /// The requests made following the redirects, in the order they were made.
+    ///
+    /// Missing in files recorded before redirects were followed by `ReplayClient` itself,
+    /// in which case `response` is the final response of the chain.
+    #[serde(default)]
+    redirects: Vec<RecordedExchange>,
+}
+
+impl Recording {
+    /// The names of the body files of the responses, in the order of the chain.
+    fn body_files_mut(&mut self) -> Vec<&mut Option<String>> {
+        Some(&mut self.body_file)
+            .into_iter()
+            .chain(self.redirects.iter_mut().map(|e| &mut e.body_file))
+            .collect()
+    }
+
+    /// The names of the body files there are.
+    fn body_files(&self) -> Vec<&String> {
+        self.body_file
+            .iter()
+            .chain(self.redirects.iter().filter_map(|e| e.body_file.as_ref()))
+            .collect()
+    }
+
+    /// Return the recorded response to one request of the chain starting with `first`, and
+    /// its body file.
+    fn response_to(
+        &self,
+        first: &RequestMem,
+        request: &RequestMem,
+    ) -> Option<(&Response, Option<&String>)> {
+        if *request == *first {
+            return Some((&self.response, self.body_file.as_ref()));
+        }
+        self.redirects
+            .iter()
+            .find(|exchange| exchange.request == *request)
+            .map(|exchange| (&exchange.response, exchange.body_file.as_ref()))
+    }
+}
+
+/// A request and its response.
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedExchange {
+    request: RequestMem,
+    response: Response,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_file: Option<String>,
+}
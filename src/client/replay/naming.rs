@@ -0,0 +1,218 @@
+//! How the replay files in a `RecordingTarget::Dir` are named.
+
+use error::{Error, ErrorKind};
+use reqwest::{Method, Url};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// The maximal length of the slug of the URL path in `FileNaming::Readable`.
+const MAX_SLUG_LEN: usize = 60;
+
+/// Specifies the names of the replay files in a `RecordingTarget::Dir`.
+///
+/// Every name includes the digest of the request unless a `Custom` naming leaves it out,
+/// which is what makes it unique. The index file of the directory lists the method and URL of
+/// every file, whatever the naming.
+#[derive(Clone, Default)]
+pub enum FileNaming {
+    /// `{hash}.json`, where `hash` is the digest of the request. This is the default.
+    #[default]
+    Hash,
+
+    /// `{host}/{method}_{path}_{hash}.json`, where `path` is a slug of the path of the URL,
+    /// e. g. `example.com/GET_api-users_0123456789abcdef.json`. An explicit port is appended
+    /// to the host like `localhost_8080`.
+    Readable,
+
+    /// Name the file using the provided function, which returns its path relative to the
+    /// directory, without the `.json` extension.
+    ///
+    /// The path must not leave the directory. Different requests must be given different
+    /// paths, otherwise they replace each other's recordings.
+    Custom(Arc<dyn Fn(&FileNameRequest) -> PathBuf + Send + Sync>),
+}
+
+impl FileNaming {
+    /// Create a `FileNaming::Custom` from the provided function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reqwest_mock::client::FileNaming;
+    ///
+    /// // api/GET/users/0123456789abcdef.json
+    /// let naming = FileNaming::custom(|request| {
+    ///     let mut path = std::path::PathBuf::from("api");
+    ///     path.push(request.method().as_str());
+    ///     path.push(request.path_slug());
+    ///     path.push(request.hash());
+    ///     path
+    /// });
+    /// ```
+    pub fn custom<F>(naming: F) -> Self
+    where
+        F: Fn(&FileNameRequest) -> PathBuf + Send + Sync + 'static,
+    {
+        FileNaming::Custom(Arc::new(naming))
+    }
+
+    /// The path of the file for `request` relative to the directory, including the extension.
+    pub(super) fn relative_path(&self, request: &FileNameRequest) -> Result<PathBuf, Error> {
+        let path = match *self {
+            FileNaming::Hash => PathBuf::from(request.hash()),
+            FileNaming::Readable => {
+                let mut path = PathBuf::from(request.host_slug());
+                path.push(format!(
+                    "{}_{}_{}",
+                    request.method(),
+                    request.path_slug(),
+                    request.hash()
+                ));
+                path
+            }
+            FileNaming::Custom(ref naming) => naming(request),
+        };
+
+        check_relative(&path)?;
+        let mut path = path.into_os_string();
+        path.push(".json");
+        Ok(PathBuf::from(path))
+    }
+}
+
+/// Check that `path` is a relative path which stays inside of the directory it is relative to.
+pub(super) fn check_relative(path: &Path) -> Result<(), Error> {
+    let valid = path.components().count() > 0
+        && path.components().all(|c| matches!(c, Component::Normal(_)));
+    if valid {
+        Ok(())
+    } else {
+        Err(ErrorKind::InvalidReplayPath(path.to_path_buf()).into())
+    }
+}
+
+impl fmt::Debug for FileNaming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FileNaming::Hash => write!(f, "Hash"),
+            FileNaming::Readable => write!(f, "Readable"),
+            FileNaming::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// The request a replay file is named for by `FileNaming::Custom`.
+#[derive(Debug)]
+pub struct FileNameRequest<'a> {
+    method: &'a Method,
+    url: &'a Url,
+    hash: String,
+}
+
+impl<'a> FileNameRequest<'a> {
+    pub(super) fn new(method: &'a Method, url: &'a Url, hash: String) -> Self {
+        FileNameRequest { method, url, hash }
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        self.method
+    }
+
+    /// The URL of the request.
+    pub fn url(&self) -> &Url {
+        self.url
+    }
+
+    /// The digest of the request as 16 hex digits, which identifies it including its headers
+    /// and body and never changes.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// The path of the URL as a string of lowercase letters, digits, dots and dashes which is
+    /// safe to use in a file name, `index` for the root path.
+    pub fn path_slug(&self) -> String {
+        let slug = slug(self.url.path());
+        if slug.is_empty() {
+            "index".to_string()
+        } else {
+            slug
+        }
+    }
+
+    /// The host of the URL, followed by the port if there is an explicit one.
+    fn host_slug(&self) -> String {
+        let host = slug(self.url.host_str().unwrap_or("unknown"));
+        match self.url.port() {
+            Some(port) => format!("{}_{}", host, port),
+            None => host,
+        }
+    }
+}
+
+/// Replace every run of characters other than ASCII letters, digits and dots by a dash, and
+/// shorten the result.
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let mut slug = slug.trim_end_matches('-').to_string();
+    slug.truncate(MAX_SLUG_LEN);
+    // Leading dots would hide the file, and `..` would leave the directory.
+    slug.trim_start_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(naming: &FileNaming, method: Method, url: &str) -> String {
+        let url = Url::parse(url).unwrap();
+        let request = FileNameRequest::new(&method, &url, "0123456789abcdef".to_string());
+        naming
+            .relative_path(&request)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    #[test]
+    fn readable() {
+        let naming = FileNaming::Readable;
+        assert_eq!(
+            path(
+                &naming,
+                Method::GET,
+                "https://example.com/api/Users/42?page=2"
+            ),
+            "example.com/GET_api-users-42_0123456789abcdef.json"
+        );
+        assert_eq!(
+            path(&naming, Method::POST, "http://localhost:8080/"),
+            "localhost_8080/POST_index_0123456789abcdef.json"
+        );
+        assert_eq!(
+            path(&naming, Method::GET, "http://example.com/.hidden/a b/v1.2"),
+            "example.com/GET_hidden-a-20b-v1.2_0123456789abcdef.json"
+        );
+        assert_eq!(
+            path(&FileNaming::Hash, Method::GET, "http://example.com/"),
+            "0123456789abcdef.json"
+        );
+    }
+
+    #[test]
+    fn custom_outside() {
+        let naming = FileNaming::custom(|_| PathBuf::from("../outside"));
+        let url = Url::parse("http://example.com/").unwrap();
+        let request = FileNameRequest::new(&Method::GET, &url, String::new());
+        assert!(naming.relative_path(&request).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Writing replay files atomically, and locking them against concurrent access.
+//!
+//! Tests run in parallel threads and processes, which might record the same request at the
+//! same time. Files are never written in place but next to their destination and then
+//! renamed, so a reader never sees half a file, not even after a crash. An advisory lock on
+//! the directory keeps readers from combining a replay file with the body files of another
+//! recording of the same request.
+//!
+//! A `RecordingTarget::Dir` also has an index, which is updated under the lock as well, and
+//! the replay files used are listed in a usage file for pruning the unused ones.
+
+use error::Error;
+use fs2::FileExt;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, create_dir_all, read_dir, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::{Builder, NamedTempFile};
+
+/// The name of the lock file created in every directory containing replay files.
+const LOCK_FILE: &str = ".reqwest_mock.lock";
+
+/// The directory containing `file`.
+pub(super) fn dir_of(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// An advisory lock on the replay files of a directory, which is released when dropped.
+///
+/// Hold a shared lock while reading a replay file and opening its body files, and an
+/// exclusive one while replacing them.
+pub(super) struct DirLock {
+    /// None if the directory is read without a lock.
+    file: Option<File>,
+}
+
+impl DirLock {
+    /// Lock the directory for reading, none if it doesn't exist, as there is nothing to read.
+    ///
+    /// This only needs read access, so recordings can be replayed from a read-only directory.
+    /// If the lock file doesn't exist and can't be created either, the directory is read
+    /// without a lock.
+    pub fn shared(dir: &Path) -> Result<Option<Self>, Error> {
+        if !dir.exists() {
+            return Ok(None);
+        }
+        let path = dir.join(LOCK_FILE);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => match Self::create(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    warn!(
+                        "reqwest_mock: Reading replay files in {:?} without a lock, the lock \
+                         file can't be created: {}",
+                        dir, e
+                    );
+                    return Ok(Some(DirLock { file: None }));
+                }
+            },
+            Err(e) => return Err(e.into()),
+        };
+        FileExt::lock_shared(&file)?;
+        Ok(Some(DirLock { file: Some(file) }))
+    }
+
+    /// Lock the directory for writing, creating it if it doesn't exist.
+    pub fn exclusive(dir: &Path) -> Result<Self, Error> {
+        create_dir_all(dir)?;
+        let file = Self::create(&dir.join(LOCK_FILE))?;
+        FileExt::lock_exclusive(&file)?;
+        Ok(DirLock { file: Some(file) })
+    }
+
+    fn create(path: &Path) -> io::Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        if let Some(ref file) = self.file {
+            let _ = FileExt::unlock(file);
+        }
+    }
+}
+
+/// A file written next to its destination, which replaces the destination at once when it
+/// is persisted. It is deleted if it is dropped before.
+pub(super) struct PendingFile {
+    temp: NamedTempFile,
+    path: PathBuf,
+}
+
+impl PendingFile {
+    /// Start writing a file which will replace `path`, creating its directory if needed.
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let dir = dir_of(path);
+        create_dir_all(&dir)?;
+        let temp = Builder::new().prefix(".reqwest_mock").tempfile_in(dir)?;
+        Ok(PendingFile {
+            temp,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The file to write the content to.
+    pub fn file(&mut self) -> &mut File {
+        self.temp.as_file_mut()
+    }
+
+    /// Open the written content for reading, the file stays readable after persisting it.
+    pub fn reopen(&self) -> Result<File, Error> {
+        Ok(self.temp.reopen()?)
+    }
+
+    /// The file name of the destination.
+    pub fn file_name(&self) -> Option<String> {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
+    /// Change the destination, which must be in the same directory.
+    pub fn set_path(&mut self, path: PathBuf) {
+        debug_assert_eq!(dir_of(&path), dir_of(&self.path));
+        self.path = path;
+    }
+
+    /// Replace the destination by the written file.
+    pub fn persist(self) -> Result<(), Error> {
+        self.temp.as_file().sync_all()?;
+        self.temp.persist(&self.path).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+/// The name of the index file of a `RecordingTarget::Dir`, listing the method and URL of the
+/// request recorded in every replay file, for browsing them.
+pub(super) const INDEX_FILE: &str = "index.json";
+
+/// The entry of a replay file in the index.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct IndexEntry {
+    pub hash: String,
+    pub method: String,
+    pub url: String,
+}
+
+/// The index of a directory, by the paths of the replay files relative to it.
+pub(super) type Index = BTreeMap<String, IndexEntry>;
+
+/// The path of `file` relative to `dir` as used in the index, with forward slashes.
+pub(super) fn index_key(dir: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(dir).unwrap_or(file);
+    let parts: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    parts.join("/")
+}
+
+/// Read the index of `dir`, an empty one if there is none or it can't be read, as it can be
+/// built again.
+pub(super) fn read_index(dir: &Path) -> Index {
+    let path = dir.join(INDEX_FILE);
+    File::open(&path)
+        .ok()
+        .and_then(|f| match ::serde_json::from_reader(f) {
+            Ok(index) => Some(index),
+            Err(e) => {
+                warn!("reqwest_mock: Ignoring unreadable index {:?}: {}", path, e);
+                None
+            }
+        })
+        .unwrap_or_default()
+}
+
+/// Replace the index of `dir`, the directory must be locked exclusively.
+pub(super) fn write_index(dir: &Path, index: &Index) -> Result<(), Error> {
+    let mut pending = PendingFile::create(&dir.join(INDEX_FILE))?;
+    ::serde_json::to_writer_pretty(pending.file(), index)?;
+    pending.persist()
+}
+
+/// The replay files in `dir` and its subdirectories, sorted. Hidden files and the index are
+/// skipped.
+pub(super) fn replay_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    fn walk(dir: &Path, root: bool, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if name.starts_with('.') || (root && name == INDEX_FILE) {
+                continue;
+            }
+            if path.is_dir() {
+                walk(&path, false, files)?;
+            } else if name.ends_with(".json") {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    if dir.exists() {
+        walk(dir, true, &mut files)?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The name of the file listing the replay files used since the last prune, one index key
+/// per line. Clients in every process append to it.
+const USAGE_FILE: &str = ".reqwest_mock.used";
+
+/// Record that the replay file with the index key `key` in `dir` was used, the directory
+/// must be locked.
+pub(super) fn append_usage(dir: &Path, key: &str) -> Result<(), Error> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(USAGE_FILE))?;
+    // A single write, so lines appended concurrently under a shared lock don't mix.
+    f.write_all(format!("{}\n", key).as_bytes())?;
+    Ok(())
+}
+
+/// The index keys of the replay files in `dir` used since the last prune, none if no usage
+/// was recorded.
+pub(super) fn read_usage(dir: &Path) -> Result<Option<BTreeSet<String>>, Error> {
+    let path = dir.join(USAGE_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    Ok(Some(content.lines().map(str::to_string).collect()))
+}
+
+/// Forget which replay files in `dir` were used, the directory must be locked exclusively.
+pub(super) fn reset_usage(dir: &Path) -> Result<(), Error> {
+    let path = dir.join(USAGE_FILE);
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// The body files next to the replay file `file`, named `{stem}.{hop}.body` for the first
+/// response recorded and `{stem}.{position}-{hop}.body` for the following ones.
+pub(super) fn body_files(file: &Path) -> Result<Vec<PathBuf>, Error> {
+    fn is_number(s: &str) -> bool {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+    }
+
+    let stem = match file.file_stem() {
+        Some(stem) => format!("{}.", stem.to_string_lossy()),
+        None => return Ok(Vec::new()),
+    };
+    let mut files = Vec::new();
+    for entry in read_dir(dir_of(file))? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let index = name
+            .strip_prefix(&stem[..])
+            .and_then(|rest| rest.strip_suffix(".body"));
+        let valid = index.is_some_and(|i| match i.split_once('-') {
+            Some((position, hop)) => is_number(position) && is_number(hop),
+            None => is_number(i),
+        });
+        if valid {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
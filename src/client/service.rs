@@ -0,0 +1,207 @@
+//! Interoperability with `tower`: every client can be used as a `Service` through
+//! `ClientService`, and every HTTP `Service` can be used as a client through `ServiceClient`.
+//!
+//! The clients are blocking. `ClientService` runs their requests on the blocking threads of
+//! the `tokio` runtime, so it must be called on a thread of one. `ServiceClient` waits for the
+//! futures of the service, it may not run on a thread of an async runtime, use e. g.
+//! `tokio::task::spawn_blocking` to call it from async code.
+
+use body::Body;
+use bytes::Buf;
+use client::Client;
+use config::ClientConfig;
+use error::{Error, ErrorKind};
+use http::{Request as HttpRequest, Response as HttpResponse, Uri};
+use http_body::Body as HttpBody;
+use request::{Request, RequestMem};
+use response::Response;
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::future::{poll_fn, Future};
+use std::marker::PhantomData;
+use std::panic;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::{self, JoinHandle};
+use tower_service::Service;
+
+/// A `tower` HTTP service performing the requests with a client, using its config.
+///
+/// Every request is executed by a task of `tokio::task::spawn_blocking`, so `call` panics when
+/// not called on a thread of a `tokio` runtime. Dropping the future, e. g. by the `Timeout`
+/// middleware, doesn't interrupt the request, the task runs on until the client is done.
+pub struct ClientService<C> {
+    client: Arc<C>,
+}
+
+impl<C> ClientService<C> {
+    /// Create a new `ClientService` performing requests with `client`.
+    pub fn new(client: C) -> Self {
+        ClientService {
+            client: Arc::new(client),
+        }
+    }
+
+    /// The client performing the requests, e. g. to check the recordings a `ReplayClient` used.
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+}
+
+impl<C> Clone for ClientService<C> {
+    fn clone(&self) -> Self {
+        ClientService {
+            client: self.client.clone(),
+        }
+    }
+}
+
+impl<C: Client + Send + Sync + 'static> Service<HttpRequest<Body>> for ClientService<C> {
+    type Response = HttpResponse<Vec<u8>>;
+    type Error = Error;
+    type Future = ClientServiceFuture;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: HttpRequest<Body>) -> Self::Future {
+        let client = self.client.clone();
+        ClientServiceFuture(task::spawn_blocking(move || {
+            Request::try_from(request)
+                .and_then(|request| client.execute(None, request))
+                .map(HttpResponse::from)
+        }))
+    }
+}
+
+/// The response of a `ClientService`, which is ready when the blocking task is done.
+pub struct ClientServiceFuture(JoinHandle<Result<HttpResponse<Vec<u8>>, Error>>);
+
+impl Future for ClientServiceFuture {
+    type Output = Result<HttpResponse<Vec<u8>>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match Pin::new(&mut self.0).poll(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(error)) if error.is_panic() => panic::resume_unwind(error.into_panic()),
+            Poll::Ready(Err(error)) => {
+                Poll::Ready(Err(Error::with_source(ErrorKind::Request, error)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A client sending the requests to a `tower` HTTP service, e. g. an in-process router.
+///
+/// `B` is the type of the request bodies the service takes. The service is cloned for every
+/// request, and its futures are run to completion on the calling thread by a `tokio` runtime
+/// started for the request, so they can use its timers and I/O, e. g. the handlers of an `axum`
+/// router. Tasks they spawn are dropped when the response was read. Redirects are followed
+/// according to the `RedirectPolicy` of the config, the other settings of the config only apply
+/// to clients performing actual requests.
+pub struct ServiceClient<S, B = Vec<u8>> {
+    config: ClientConfig,
+    service: S,
+    _body: PhantomData<fn(B)>,
+}
+
+impl<S, B> ServiceClient<S, B> {
+    /// Create a new `ServiceClient` sending requests to `service`.
+    pub fn new(service: S) -> Self {
+        ServiceClient {
+            config: ClientConfig::default(),
+            service,
+            _body: PhantomData,
+        }
+    }
+}
+
+impl<S, ReqB, ResB> ServiceClient<S, ReqB>
+where
+    S: Service<HttpRequest<ReqB>, Response = HttpResponse<ResB>> + Clone,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    ReqB: From<Vec<u8>>,
+    ResB: HttpBody,
+    ResB::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Send a single request to the service.
+    fn call(&self, request: &RequestMem) -> Result<Response, Error> {
+        let mut http_req = HttpRequest::new(ReqB::from(
+            request
+                .body
+                .as_ref()
+                .and_then(|body| body.bytes())
+                .map(<[u8]>::to_vec)
+                .unwrap_or_default(),
+        ));
+        *http_req.method_mut() = request.header.method.clone();
+        *http_req.uri_mut() = Uri::try_from(request.header.url.as_str())
+            .map_err(|e| Error::invalid_url(request.header.url.as_str(), e))?;
+        *http_req.headers_mut() = request.header.headers.clone();
+
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        // Services may spawn tasks when called, not only when their futures are polled.
+        let _runtime = runtime.enter();
+        let mut service = self.service.clone();
+        block_on(&runtime, |cx| service.poll_ready(cx)).map_err(service_error)?;
+        let mut future = Box::pin(service.call(http_req));
+        let (parts, body) = block_on(&runtime, |cx| future.as_mut().poll(cx))
+            .map_err(service_error)?
+            .into_parts();
+
+        let mut body = Box::pin(body);
+        let mut bytes = Vec::new();
+        while let Some(data) = block_on(&runtime, |cx| body.as_mut().poll_data(cx)) {
+            let mut data = data.map_err(service_error)?;
+            while data.has_remaining() {
+                let chunk = data.chunk();
+                bytes.extend_from_slice(chunk);
+                let n = chunk.len();
+                data.advance(n);
+            }
+        }
+
+        let response = HttpResponse::from_parts(parts, bytes);
+        Ok(Response::from_http(response, request.header.url.clone()))
+    }
+}
+
+impl<S, ReqB, ResB> Client for ServiceClient<S, ReqB>
+where
+    S: Service<HttpRequest<ReqB>, Response = HttpResponse<ResB>> + Clone,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    ReqB: From<Vec<u8>>,
+    ResB: HttpBody,
+    ResB::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+        // Use internal config if none was provided together with the request.
+        let config = config.unwrap_or(&self.config);
+
+        ::redirect::follow(config, request.into_mem(None)?, |req| self.call(req))
+    }
+
+    fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        &mut self.config
+    }
+}
+
+fn service_error<E: Into<Box<dyn StdError + Send + Sync>>>(error: E) -> Error {
+    Error::with_source(ErrorKind::Request, error)
+}
+
+/// Call `poll` on `runtime` until it is ready, blocking the thread in between.
+fn block_on<T, F>(runtime: &Runtime, poll: F) -> T
+where
+    F: FnMut(&mut Context) -> Poll<T>,
+{
+    runtime.block_on(poll_fn(poll))
+}
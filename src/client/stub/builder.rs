@@ -1,6 +1,8 @@
 use body::Body;
 use client::stub::error::RegisterStubError;
-use client::stub::{StubClient, StubRequest, StubResponse};
+use client::stub::{StubClient, StubRequest, StubResponse, StubStrictness};
+use client::{Client, DirectClient};
+use multipart::PartMatcher;
 use reqwest::header::{HeaderMap, HeaderValue, IntoHeaderName};
 use reqwest::{Method, StatusCode, Url};
 
@@ -10,22 +12,24 @@ use reqwest::{Method, StatusCode, Url};
 /// return a `ResponseStubber` instance and start specifying the response. Finally use
 /// `ResponseStubber::mock()` to register the mock into the client.
 #[must_use]
-pub struct RequestStubber<'cl> {
-    client: &'cl mut StubClient,
+pub struct RequestStubber<'cl, C: Client = DirectClient> {
+    client: &'cl mut StubClient<C>,
     url: Url,
 
     _method: Option<Method>,
     _body: Option<Body>,
+    _parts: Vec<PartMatcher>,
     _headers: Option<HeaderMap>,
 }
 
-impl<'cl> RequestStubber<'cl> {
-    pub(super) fn new(client: &'cl mut StubClient, url: Url) -> RequestStubber<'cl> {
+impl<'cl, C: Client> RequestStubber<'cl, C> {
+    pub(super) fn new(client: &'cl mut StubClient<C>, url: Url) -> Self {
         RequestStubber {
-            client: client,
-            url: url,
+            client,
+            url,
             _method: None,
             _body: None,
+            _parts: Vec::new(),
             _headers: None,
         }
     }
@@ -37,38 +41,101 @@ impl<'cl> RequestStubber<'cl> {
     }
 
     /// Set the body of the request.
+    ///
+    /// This replaces any part matchers added with `part`.
     pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
         self._body = Some(body.into());
+        self._parts.clear();
+        self
+    }
+
+    /// Match the request by a part of its `multipart/form-data` body, instead of the whole body.
+    ///
+    /// Can be called multiple times, all of the matchers have to match a part of the request.
+    /// Parts of the request not matched by any matcher are ignored. This replaces any body set
+    /// with `body`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reqwest_mock::multipart::{Form, PartMatcher};
+    /// use reqwest_mock::{Client, Method, StubClient, StubSettings, StubStrictness, StubDefault, Url};
+    ///
+    /// let mut client = StubClient::new(StubSettings {
+    ///     default: StubDefault::Error,
+    ///     strictness: StubStrictness::BodyMethodUrl,
+    /// });
+    /// client
+    ///     .stub(Url::parse("http://example.com/upload").unwrap())
+    ///         .method(Method::POST)
+    ///         .part(PartMatcher::new("title").text("Holiday"))
+    ///     .response()
+    ///         .body("uploaded")
+    ///         .mock()
+    ///         .unwrap();
+    ///
+    /// let form = Form::new().text("title", "Holiday").text("tags", "beach");
+    /// let response = client
+    ///     .post("http://example.com/upload")
+    ///     .multipart(form)
+    ///     .send()
+    ///     .unwrap();
+    /// assert_eq!(response.body_to_utf8().unwrap(), "uploaded");
+    /// ```
+    pub fn part(mut self, matcher: PartMatcher) -> Self {
+        self._body = None;
+        self._parts.push(matcher);
         self
     }
 
     /// Add a header to the request.
+    ///
+    /// The default headers of the client's `ClientConfig` are included automatically.
     pub fn header<HN: IntoHeaderName>(mut self, name: HN, value: HeaderValue) -> Self {
-        self._headers = Some(self._headers.map_or_else(HeaderMap::new, |mut hs| {
-            hs.insert(name, value);
-            hs
-        }));
+        self._headers
+            .get_or_insert_with(HeaderMap::new)
+            .insert(name, value);
         self
     }
 
     /// Add multiple headers to the request.
+    ///
+    /// The default headers of the client's `ClientConfig` are included automatically.
     pub fn headers(mut self, headers: HeaderMap) -> Self {
-        self._headers = Some(self._headers.map_or_else(HeaderMap::new, |mut hs| {
-            hs.extend(headers);
-            hs
-        }));
+        self._headers
+            .get_or_insert_with(HeaderMap::new)
+            .extend(headers);
         self
     }
 
     /// Stub the response to this request.
-    pub fn response(self) -> ResponseStubber<'cl> {
+    ///
+    /// If the stubs are matched by headers, the default headers of the client's `ClientConfig`
+    /// are included in the headers of the request, even if none were added.
+    pub fn response(self) -> ResponseStubber<'cl, C> {
+        // Requests made through the client include the default headers, so the stub has to
+        // as well for them to match, even if it sets none of its own.
+        let client = self.client;
+        let matches_headers = matches!(
+            client.settings.strictness,
+            StubStrictness::Full | StubStrictness::HeadersMethodUrl
+        );
+        let headers = if self._headers.is_some() || matches_headers {
+            let mut headers = client.config.default_request_headers();
+            headers.extend(self._headers.unwrap_or_default());
+            Some(::helper::serialize_headers(&headers))
+        } else {
+            None
+        };
+
         ResponseStubber {
-            client: self.client,
+            client,
             req: StubRequest {
                 url: self.url,
                 method: self._method,
                 body: self._body,
-                headers: self._headers.map(|hs| ::helper::serialize_headers(&hs)),
+                parts: self._parts,
+                headers,
             },
 
             _status_code: StatusCode::OK,
@@ -80,8 +147,8 @@ impl<'cl> RequestStubber<'cl> {
 
 /// A response stub builder to be used in conjunction with `StubClient`.
 #[must_use]
-pub struct ResponseStubber<'cl> {
-    client: &'cl mut StubClient,
+pub struct ResponseStubber<'cl, C: Client = DirectClient> {
+    client: &'cl mut StubClient<C>,
     req: StubRequest,
 
     _status_code: StatusCode,
@@ -89,7 +156,7 @@ pub struct ResponseStubber<'cl> {
     _headers: HeaderMap,
 }
 
-impl<'cl> ResponseStubber<'cl> {
+impl<'cl, C: Client> ResponseStubber<'cl, C> {
     /// Set the status code of the response.
     pub fn status_code(mut self, status: StatusCode) -> Self {
         self._status_code = status;
@@ -97,6 +164,9 @@ impl<'cl> ResponseStubber<'cl> {
     }
 
     /// Set the body of the response.
+    ///
+    /// A `File` is not read until the response is requested, and then read again for every
+    /// request.
     pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
         self._body = Some(body.into());
         self
@@ -121,10 +191,11 @@ impl<'cl> ResponseStubber<'cl> {
             body: self._body,
             headers: self._headers,
         };
+        let body_limit = self.client.config.max_buffered_body;
         self.client.register_stub(
             self.req
-                .try_to_key()
-                .map_err(|e| RegisterStubError::ReadFile(e))?,
+                .try_to_key(body_limit)
+                .map_err(RegisterStubError::read_file)?,
             resp,
         )
     }
@@ -1,24 +1,87 @@
+use std::error::Error as StdError;
+use std::fmt;
 use std::io;
 
-// TODO: Hide what is not needed.
-// TODO: impl Error
+/// The error returned when a stub could not be registered.
+#[derive(Debug)]
+pub struct RegisterStubError {
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    MissingField(FieldError),
+    UnnecessaryField(FieldError),
+    ReadFile(io::Error),
+}
 
-// TODO: Should be crate or module visible.
 #[derive(Debug)]
-pub struct FieldError {
+pub(crate) struct FieldError {
     /// The name of the missing field.
     pub field_name: &'static str,
     /// The strictness level, which implied this field being required.
     pub strictness: &'static str,
 }
 
-// TODO: Variants should be private.
-#[derive(Debug)]
-pub enum RegisterStubError {
-    // "Tried registering stub without `{}` even though `{}` requires its presence."
-    MissingField(FieldError),
+impl RegisterStubError {
+    /// The stub lacks a field `strictness` requires.
+    pub(crate) fn missing_field(field: FieldError) -> Self {
+        RegisterStubError {
+            kind: Kind::MissingField(field),
+        }
+    }
 
-    // "Tried registering stub with `{}` in the request, even though `{}` means you don't want to check it in requests. Please remove the field or set a higher `StubStrictness`."
-    UnescessaryField(FieldError),
-    ReadFile(io::Error),
+    /// The stub has a field `strictness` doesn't check.
+    pub(crate) fn unnecessary_field(field: FieldError) -> Self {
+        RegisterStubError {
+            kind: Kind::UnnecessaryField(field),
+        }
+    }
+
+    /// Reading the body of the stubbed request or response failed.
+    pub(crate) fn read_file(error: io::Error) -> Self {
+        RegisterStubError {
+            kind: Kind::ReadFile(error),
+        }
+    }
+
+    /// The name of the field which is missing or unnecessary for the `StubStrictness` in use,
+    /// none if reading a body failed instead.
+    pub fn field_name(&self) -> Option<&'static str> {
+        match self.kind {
+            Kind::MissingField(ref field) | Kind::UnnecessaryField(ref field) => {
+                Some(field.field_name)
+            }
+            Kind::ReadFile(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RegisterStubError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::MissingField(ref field) => write!(
+                f,
+                "Tried registering stub without `{}` even though `{}` requires its presence.",
+                field.field_name, field.strictness
+            ),
+            Kind::UnnecessaryField(ref field) => write!(
+                f,
+                "Tried registering stub with `{}` in the request, even though `{}` means you \
+                 don't want to check it in requests. Please remove the field or set a higher \
+                 `StubStrictness`.",
+                field.field_name, field.strictness
+            ),
+            Kind::ReadFile(_) => write!(f, "Failed reading the body of the stub."),
+        }
+    }
+}
+
+impl StdError for RegisterStubError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self.kind {
+            Kind::ReadFile(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
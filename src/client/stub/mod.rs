@@ -1,12 +1,16 @@
-use body::Body;
-use client::Client;
-use config::ClientConfig;
-use error::Error;
-use request::{Request, RequestHeader};
-use reqwest::header::HeaderMap;
+use body::{Body, BodyMem, SharedFile};
+use client::{Client, DirectClient};
+use config::{ClientConfig, RedirectPolicy};
+use diff::RequestDiff;
+use error::{Error, ErrorKind};
+use multipart::PartMatcher;
+use request::{Request, RequestHeader, RequestMem};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
 use reqwest::{Method, StatusCode, Url};
-use response::Response;
+use response::{Response, StreamingResponse};
 use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Seek};
+use std::sync::Arc;
 
 mod settings;
 pub use self::settings::{StubDefault, StubSettings, StubStrictness};
@@ -15,34 +19,95 @@ mod builder;
 pub use self::builder::{RequestStubber, ResponseStubber};
 
 mod error;
+use self::error::FieldError;
 pub use self::error::RegisterStubError;
-// TODO should not be public
-pub use self::error::FieldError;
+
+/// The number of stubs listed in the error if no stub matches a request.
+const CLOSEST_STUBS: usize = 3;
 
 #[derive(Hash, PartialEq, Eq)]
 struct StubKey {
     url: Url,
     method: Option<Method>,
-    body: Option<Vec<u8>>,
+    body: Option<BodyMem>,
+    /// Matchers for the parts of a multipart body, used instead of `body` if not empty.
+    parts: Vec<PartMatcher>,
     headers: Option<BTreeMap<String, String>>,
 }
 
+impl StubKey {
+    /// Check if the stub, which has to match by `parts`, matches the request `key` belongs to.
+    fn matches_parts(&self, key: &StubKey, content_type: Option<&HeaderValue>) -> bool {
+        self.url == key.url
+            && self.method == key.method
+            && self.headers == key.headers
+            && self.parts_match(key.body.as_ref(), content_type)
+    }
+
+    /// Check if every part matcher of the stub matches a part of the multipart `body`.
+    fn parts_match(&self, body: Option<&BodyMem>, content_type: Option<&HeaderValue>) -> bool {
+        let boundary = content_type
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(::multipart::boundary);
+        let parsed = match (boundary, body.and_then(BodyMem::bytes)) {
+            (Some(boundary), Some(body)) => ::multipart::parse(body, boundary),
+            _ => None,
+        };
+        match parsed {
+            Some(parsed) => self
+                .parts
+                .iter()
+                .all(|matcher| parsed.iter().any(|part| matcher.matches(part))),
+            None => false,
+        }
+    }
+
+    /// How `request` differs from the stub, comparing only the fields the stub checks.
+    fn diff(&self, request: &RequestMem) -> RequestDiff {
+        let mut diff = RequestDiff::expecting(self.method.clone(), self.url.clone());
+        if let Some(ref method) = self.method {
+            diff.method(method, &request.header.method);
+        }
+        diff.url(&self.url, &request.header.url);
+        if let Some(ref headers) = self.headers {
+            diff.headers(
+                headers,
+                &::helper::serialize_headers(&request.header.headers),
+            );
+        }
+        if !self.parts.is_empty() {
+            let content_type = request.header.headers.get(CONTENT_TYPE);
+            if !self.parts_match(request.body.as_ref(), content_type) {
+                diff.body_mismatch(
+                    format!("multipart form with parts matching {:?}", self.parts),
+                    request.body.as_ref(),
+                );
+            }
+        } else if self.body.is_some() {
+            diff.body(self.body.as_ref(), request.body.as_ref());
+        }
+        diff
+    }
+}
+
 struct StubRequest {
     url: Url,
     method: Option<Method>,
     body: Option<Body>,
+    parts: Vec<PartMatcher>,
     headers: Option<BTreeMap<String, String>>,
 }
 
 impl StubRequest {
-    fn try_to_key(self) -> Result<StubKey, ::std::io::Error> {
+    fn try_to_key(self, body_limit: Option<u64>) -> Result<StubKey, ::std::io::Error> {
         Ok(StubKey {
             url: self.url,
             method: self.method,
             body: match self.body {
-                Some(b) => Some(b.try_to_vec()?),
+                Some(b) => Some(b.into_mem(body_limit)?),
                 None => None,
             },
+            parts: self.parts,
             headers: self.headers,
         })
     }
@@ -54,8 +119,45 @@ struct StubResponse {
     headers: HeaderMap,
 }
 
+/// A registered response, whose body is either kept in memory or streamed from a file.
+struct Stubbed {
+    response: Response,
+    file: Option<Arc<SharedFile>>,
+}
+
+impl Stubbed {
+    fn to_streaming(&self) -> StreamingResponse {
+        let response = &self.response;
+        match self.file {
+            Some(ref file) => StreamingResponse::new(
+                response.url.clone(),
+                response.status,
+                response.headers.clone(),
+                SharedFile::reader(file),
+            ),
+            None => StreamingResponse::new(
+                response.url.clone(),
+                response.status,
+                response.headers.clone(),
+                Cursor::new(response.body.clone()),
+            ),
+        }
+    }
+}
+
 /// A client which allows you to stub out the response to a request explicitly.
 ///
+/// Stubbed redirect responses (i. e. `3xx` responses with a `Location` header) are followed
+/// according to the `RedirectPolicy` of the client's config, so the redirect target has to be
+/// stubbed as well. If `referer` is enabled in the config, requests following a redirect
+/// carry a `Referer` header, which matters when stubs are matched by headers.
+///
+/// Response bodies stubbed with a `File` are read from the file for every request, so
+/// `send_streaming` streams them without loading the file into memory.
+///
+/// With `StubDefault::PerformRequest`, requests without a stub are passed on to a fallback
+/// client, a `DirectClient` by default, see `with_fallback` for using another client.
+///
 /// # Examples
 /// ```
 /// use reqwest_mock::{Client, Method, StubClient, StubDefault, StubSettings, StubStrictness, Url};
@@ -81,9 +183,12 @@ struct StubResponse {
 /// let response = client.get("http://example.com/mocking").send().unwrap();
 /// assert_eq!(response.body_to_utf8().unwrap(), "Mocking is fun!".to_string());
 /// ```
-pub struct StubClient {
+pub struct StubClient<C: Client = DirectClient> {
+    fallback: C,
     config: ClientConfig,
-    stubs: HashMap<StubKey, Response>,
+    stubs: HashMap<StubKey, Stubbed>,
+    /// Stubs matching multipart requests by some of their parts, checked in order.
+    part_stubs: Vec<(StubKey, Stubbed)>,
     settings: StubSettings,
 }
 
@@ -93,9 +198,38 @@ impl StubClient {
     /// Please consult [StubSettings](struct.StubSettings.html) for more information about the
     /// possible settings.
     pub fn new(stub_settings: StubSettings) -> Self {
+        Self::with_fallback(stub_settings, DirectClient::new())
+    }
+}
+
+impl<C: Client> StubClient<C> {
+    /// Create a new instance of `StubClient`, which passes requests without a stub on to
+    /// `fallback` if the default of the settings is `StubDefault::PerformRequest`, e. g. a
+    /// `ReplayClient` replaying recorded responses.
+    ///
+    /// The `StubClient` starts with the config of `fallback`. Requests are passed on together
+    /// with the config of the `StubClient`, but with redirects disabled, as the `StubClient`
+    /// follows them itself.
+    ///
+    /// # Examples
+    /// ```
+    /// use reqwest_mock::{RecordingTarget, ReplayClient, StubClient, StubDefault, StubSettings, StubStrictness};
+    ///
+    /// let replay = ReplayClient::new(RecordingTarget::dir("tests/replays"));
+    /// let client = StubClient::with_fallback(
+    ///     StubSettings {
+    ///         default: StubDefault::PerformRequest,
+    ///         strictness: StubStrictness::MethodUrl,
+    ///     },
+    ///     replay,
+    /// );
+    /// ```
+    pub fn with_fallback(stub_settings: StubSettings, fallback: C) -> Self {
         StubClient {
-            config: ClientConfig::default(),
+            config: fallback.config().clone(),
+            fallback,
             stubs: HashMap::new(),
+            part_stubs: Vec::new(),
             settings: stub_settings,
         }
     }
@@ -109,64 +243,142 @@ impl StubClient {
     /// After you are finished specifying the details of the matching request, call `response()` to
     /// return a `ResponseStubber` instance and start specifying the response. Finally use
     /// `ResponseStubber::mock()` to register the mock into the client.
-    pub fn stub<'cl>(&'cl mut self, url: Url) -> RequestStubber<'cl> {
+    pub fn stub<'cl>(&'cl mut self, url: Url) -> RequestStubber<'cl, C> {
         RequestStubber::new(self, url)
     }
 
     /// Return the appropriate `StubKey` for the provided request.
-    fn stub_key(&self, header: &RequestHeader, body: &Option<Vec<u8>>) -> StubKey {
+    fn stub_key(&self, header: &RequestHeader, body: &Option<BodyMem>) -> StubKey {
         match self.settings.strictness {
             StubStrictness::Full => StubKey {
                 url: header.url.clone(),
                 method: Some(header.method.clone()),
                 body: body.clone(),
+                parts: Vec::new(),
                 headers: Some(::helper::serialize_headers(&header.headers)),
             },
             StubStrictness::BodyMethodUrl => StubKey {
                 url: header.url.clone(),
                 method: Some(header.method.clone()),
                 body: body.clone(),
+                parts: Vec::new(),
                 headers: None,
             },
             StubStrictness::HeadersMethodUrl => StubKey {
                 url: header.url.clone(),
                 method: Some(header.method.clone()),
                 body: None,
+                parts: Vec::new(),
                 headers: Some(::helper::serialize_headers(&header.headers)),
             },
             StubStrictness::MethodUrl => StubKey {
                 url: header.url.clone(),
                 method: Some(header.method.clone()),
                 body: None,
+                parts: Vec::new(),
                 headers: None,
             },
             StubStrictness::Url => StubKey {
                 url: header.url.clone(),
                 method: None,
                 body: None,
+                parts: Vec::new(),
                 headers: None,
             },
         }
     }
 
+    /// Return the stub for a single request, taking the default action if there is none.
+    fn stub_response(
+        &self,
+        config: &ClientConfig,
+        request: &RequestMem,
+    ) -> Result<StreamingResponse, Error> {
+        let key = self.stub_key(&request.header, &request.body);
+        let content_type = request.header.headers.get(CONTENT_TYPE);
+        let stub = self.stubs.get(&key).or_else(|| {
+            self.part_stubs
+                .iter()
+                .find(|(stub, _)| stub.matches_parts(&key, content_type))
+                .map(|(_, resp)| resp)
+        });
+        match stub {
+            Some(stubbed) => Ok(stubbed.to_streaming()),
+            None => {
+                match self.settings.default {
+                    StubDefault::Panic | StubDefault::Error => {
+                        let error = Error::from(ErrorKind::NoStubMatched {
+                            method: request.header.method.clone(),
+                            url: request.header.url.clone(),
+                            headers: request.header.headers.clone(),
+                            body: request
+                                .body
+                                .as_ref()
+                                .and_then(|body| body.bytes())
+                                .map(<[u8]>::to_vec),
+                            closest: self.closest_stubs(request),
+                        });
+                        warn!("reqwest_mock: {}", error);
+                        if let StubDefault::Panic = self.settings.default {
+                            panic!("{}", error);
+                        }
+                        Err(error)
+                    }
+                    StubDefault::PerformRequest => {
+                        // Redirects are followed by `execute`, for stubbed and actual responses.
+                        let config = ClientConfig {
+                            redirect: RedirectPolicy::None,
+                            ..config.clone()
+                        };
+                        self.fallback
+                            .execute_streaming(Some(&config), request.clone().into())
+                    }
+                }
+            }
+        }
+    }
+
+    /// How `request` differs from the stubs closest to it, closest first.
+    fn closest_stubs(&self, request: &RequestMem) -> Vec<RequestDiff> {
+        let mut diffs: Vec<RequestDiff> = self
+            .stubs
+            .keys()
+            .chain(self.part_stubs.iter().map(|(key, _)| key))
+            .map(|key| key.diff(request))
+            .collect();
+        // Ties are ordered by their text, as the order of `stubs` is arbitrary.
+        diffs.sort_by_cached_key(|diff| (diff.fields().len(), diff.to_string()));
+        diffs.truncate(CLOSEST_STUBS);
+        diffs
+    }
+
     pub(self) fn register_stub(
         &mut self,
         key: StubKey,
         value: StubResponse,
     ) -> Result<(), RegisterStubError> {
         // Check if stub key contains the nescessary fields.
+        macro_rules! is_set {
+            (body) => {
+                key.body.is_some() || !key.parts.is_empty()
+            };
+            ($field:ident) => {
+                key.$field.is_some()
+            };
+        }
+
         macro_rules! validate_sk_field {
             (Some $field:ident $strictness:path) => {
-                if key.$field.is_none() {
-                    return Err(RegisterStubError::MissingField(FieldError {
+                if !is_set!($field) {
+                    return Err(RegisterStubError::missing_field(FieldError {
                         field_name: stringify!($field),
                         strictness: stringify!($strictness),
                     }));
                 }
             };
             (None $field:ident $strictness:path) => {
-                if key.$field.is_some() {
-                    return Err(RegisterStubError::UnescessaryField(FieldError {
+                if is_set!($field) {
+                    return Err(RegisterStubError::unnecessary_field(FieldError {
                         field_name: stringify!($field),
                         strictness: stringify!($strictness),
                     }));
@@ -197,63 +409,54 @@ impl StubClient {
             }
         }
 
-        // Register the response.
-        let response = Response {
-            url: key.url.clone(),
-            status: value.status_code,
-            headers: value.headers,
-            body: value
-                .body
-                .map(|b| b.try_to_vec())
-                .unwrap_or_else(|| Ok(Vec::new()))
-                .map_err(|e| RegisterStubError::ReadFile(e))?,
+        // Register the response, files are only read when the response is requested.
+        let (body, file) = match value.body.map(Body::into_file) {
+            Some(Ok(mut file)) => {
+                let start = file
+                    .stream_position()
+                    .map_err(RegisterStubError::read_file)?;
+                (Vec::new(), Some(Arc::new(SharedFile::new(file, start))))
+            }
+            Some(Err(body)) => (
+                body.try_to_vec().map_err(RegisterStubError::read_file)?,
+                None,
+            ),
+            None => (Vec::new(), None),
         };
-        self.stubs.insert(key, response);
+        let stubbed = Stubbed {
+            response: Response {
+                url: key.url.clone(),
+                status: value.status_code,
+                headers: value.headers,
+                body,
+            },
+            file,
+        };
+        if key.parts.is_empty() {
+            self.stubs.insert(key, stubbed);
+        } else {
+            self.part_stubs.push((key, stubbed));
+        }
         Ok(())
     }
 }
 
-impl Client for StubClient {
+impl<C: Client> Client for StubClient<C> {
     fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
-        // Check if there is a recorded stub for the request.
-        let header = request.header;
-        let body = match request.body {
-            Some(b) => Some(b.try_to_vec()?),
-            None => None,
-        };
+        self.execute_streaming(config, request)?.into_response()
+    }
 
-        let key = self.stub_key(&header, &body);
-        match self.stubs.get(&key) {
-            Some(resp) => Ok(resp.clone()),
-            None => {
-                match self.settings.default {
-                    StubDefault::Panic => {
-                        // TODO provide more diagonistics using log crate.
-                        panic!(
-                            "Requested {}, without having provided a stub for it.",
-                            header.url
-                        );
-                    }
-                    StubDefault::Error => {
-                        // TODO provide more diagonistics using log crate.
-                        Err(format!(
-                            "Requested {}, without having provided a stub for it.",
-                            header.url
-                        )
-                        .into())
-                    }
-                    StubDefault::PerformRequest => {
-                        use client::DirectClient;
-                        let client = DirectClient::new();
-                        let request = Request {
-                            header: header,
-                            body: body.map(Body::from),
-                        };
-                        client.execute(config, request)
-                    }
-                }
-            }
-        }
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+    ) -> Result<StreamingResponse, Error> {
+        // Use internal config if none was provided together with the request.
+        let config = config.unwrap_or(&self.config);
+
+        // Stubbed redirects are followed just like a real client would.
+        let request = request.into_mem(config.max_buffered_body)?;
+        ::redirect::follow(config, request, |req| self.stub_response(config, req))
     }
 
     fn config(&self) -> &ClientConfig {
@@ -27,7 +27,8 @@ pub enum StubStrictness {
 /// Define the default action to be taken by the `StubClient` when no matching stub is found.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum StubDefault {
-    /// Just directly perform any requests. Warning: only use this if you are sure it is ok.
+    /// Just directly perform any requests, or pass them on to the fallback client given to
+    /// `StubClient::with_fallback`. Warning: only use this if you are sure it is ok.
     PerformRequest,
 
     /// Panic if such a request is made.
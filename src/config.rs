@@ -1,5 +1,9 @@
 //! Some types used to configure a `Client` instance.
 
+use reqwest::header::{HeaderMap, HeaderValue, IntoHeaderName, USER_AGENT};
+use reqwest::{tls, Certificate, Identity, StatusCode, Url};
+use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Configures some parameters for a `Client` instance.
@@ -15,15 +19,76 @@ pub struct ClientConfig {
     /// Default will follow up to 10 redirects.
     pub redirect: RedirectPolicy,
 
-    /// Enable or disable automatic setting of the `Referer` header.
+    /// Enable or disable automatic setting of the `Referer` header when following redirects.
     ///
     /// Default is true.
-    ///
-    /// TODO: Not actually implemented yet.
     pub referer: bool,
 
-    /// Timeout for both the read and write operations of a client.
+    /// Timeout for the whole request, from connecting until the body is read.
+    ///
+    /// Default is none.
     pub timeout: Option<Duration>,
+
+    /// Timeout for each read from the connection, waiting for the response or the next part
+    /// of its body. A slow response is allowed as long as it keeps sending.
+    ///
+    /// When `timeout` is set as well, reads wait until the request times out instead.
+    /// Default is none, without any timeout reads wait for as long as the server takes.
+    pub read_timeout: Option<Duration>,
+
+    /// Timeout for establishing the connection to the server.
+    ///
+    /// Default is none.
+    pub connect_timeout: Option<Duration>,
+
+    /// Headers included in every request made through a `RequestBuilder`.
+    ///
+    /// Headers set on the request itself take precedence. Default is empty.
+    pub default_headers: HeaderMap,
+
+    /// The `User-Agent` header included in every request made through a `RequestBuilder`.
+    ///
+    /// Takes precedence over a `User-Agent` in `default_headers`. Default is none.
+    pub user_agent: Option<HeaderValue>,
+
+    /// Base URL against which the URLs passed to a `RequestBuilder` are resolved, so
+    /// relative ones like `/users` can be used.
+    ///
+    /// Resolution follows the usual rules for links, i. e. `/users` replaces the whole
+    /// path of the base URL while `users` is appended to its last directory. Default is none.
+    pub base_url: Option<Url>,
+
+    /// Additional root certificates to trust, on top of the system's trust store.
+    ///
+    /// Default is empty.
+    pub root_certificates: Vec<Certificate>,
+
+    /// Client certificate to present to servers requesting mutual TLS.
+    ///
+    /// Default is none.
+    pub identity: Option<Identity>,
+
+    /// Minimum TLS version to accept when connecting to a server.
+    ///
+    /// The native-tls backend can't require TLS 1.3, requests with it fail with
+    /// `ErrorKind::InvalidRequest`. Default is none, leaving the choice to the TLS backend.
+    pub min_tls_version: Option<tls::Version>,
+
+    /// Accept invalid server certificates, including expired, self-signed and
+    /// mismatching hostnames.
+    ///
+    /// Default is false. **Warning:** only enable this if you know what you are doing,
+    /// it makes the connection vulnerable to man-in-the-middle attacks.
+    pub danger_accept_invalid_certs: bool,
+
+    /// The size in bytes up to which `ReplayClient` and `StubClient` keep request bodies in
+    /// memory to compare them.
+    ///
+    /// Larger bodies are identified by their SHA-256 digest instead, and kept in a temporary
+    /// file if they have to be sent but can't be read again. `ReplayClient` also stores larger
+    /// response bodies in separate files instead of inside the replay file. Default is 16 MiB,
+    /// none keeps every body in memory.
+    pub max_buffered_body: Option<u64>,
 }
 
 impl Default for ClientConfig {
@@ -33,6 +98,16 @@ impl Default for ClientConfig {
             redirect: RedirectPolicy::default(),
             referer: true,
             timeout: None,
+            read_timeout: None,
+            connect_timeout: None,
+            default_headers: HeaderMap::new(),
+            user_agent: None,
+            base_url: None,
+            root_certificates: Vec::new(),
+            identity: None,
+            min_tls_version: None,
+            danger_accept_invalid_certs: false,
+            max_buffered_body: Some(16 * 1024 * 1024),
         }
     }
 }
@@ -42,14 +117,216 @@ impl ClientConfig {
         Self::default()
     }
 
-    // TODO implement builder pattern
+    /// Returns a `ClientConfigBuilder` starting out with the default config.
+    pub fn builder() -> ClientConfigBuilder {
+        ClientConfigBuilder {
+            config: Self::default(),
+        }
+    }
+
+    /// The headers every request starts out with, i. e. `default_headers` together with
+    /// `user_agent`.
+    pub(crate) fn default_request_headers(&self) -> HeaderMap {
+        let mut headers = self.default_headers.clone();
+        if let Some(ref user_agent) = self.user_agent {
+            headers.insert(USER_AGENT, user_agent.clone());
+        }
+        headers
+    }
 }
 
-/// Specifies how to hande redirects.
+/// Builds a `ClientConfig` instance.
+///
+/// # Examples
+///
+/// ```
+/// use reqwest_mock::config::ClientConfig;
+/// use reqwest_mock::Url;
+/// use std::time::Duration;
+///
+/// let config = ClientConfig::builder()
+///     .base_url(Url::parse("https://api.example.com").unwrap())
+///     .user_agent("MyClient/1.0".parse().unwrap())
+///     .connect_timeout(Duration::from_secs(5))
+///     .timeout(Duration::from_secs(30))
+///     .build();
+/// ```
 #[derive(Clone, Debug)]
+#[must_use]
+pub struct ClientConfigBuilder {
+    config: ClientConfig,
+}
+
+impl ClientConfigBuilder {
+    /// Enable or disable auto gzip decompression.
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.config.gzip = enable;
+        self
+    }
+
+    /// Set the `RedirectPolicy`.
+    pub fn redirect(mut self, policy: RedirectPolicy) -> Self {
+        self.config.redirect = policy;
+        self
+    }
+
+    /// Enable or disable automatic setting of the `Referer` header.
+    pub fn referer(mut self, enable: bool) -> Self {
+        self.config.referer = enable;
+        self
+    }
+
+    /// Set the timeout for the whole request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for each read from the connection.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for establishing the connection.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Add a header to be included in every request.
+    pub fn default_header<H: IntoHeaderName>(mut self, name: H, value: HeaderValue) -> Self {
+        self.config.default_headers.insert(name, value);
+        self
+    }
+
+    /// Add multiple headers to be included in every request.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.config.default_headers.extend(headers);
+        self
+    }
+
+    /// Set the `User-Agent` header included in every request.
+    pub fn user_agent(mut self, value: HeaderValue) -> Self {
+        self.config.user_agent = Some(value);
+        self
+    }
+
+    /// Set the base URL relative request URLs are resolved against.
+    pub fn base_url(mut self, url: Url) -> Self {
+        self.config.base_url = Some(url);
+        self
+    }
+
+    /// Trust an additional root certificate.
+    pub fn add_root_certificate(mut self, cert: Certificate) -> Self {
+        self.config.root_certificates.push(cert);
+        self
+    }
+
+    /// Set the client certificate used for mutual TLS.
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.config.identity = Some(identity);
+        self
+    }
+
+    /// Set the minimum TLS version.
+    pub fn min_tls_version(mut self, version: tls::Version) -> Self {
+        self.config.min_tls_version = Some(version);
+        self
+    }
+
+    /// Accept invalid server certificates. See `ClientConfig::danger_accept_invalid_certs`.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.config.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    /// Set the size up to which request bodies are kept in memory for comparing them.
+    pub fn max_buffered_body(mut self, limit: Option<u64>) -> Self {
+        self.config.max_buffered_body = limit;
+        self
+    }
+
+    /// Return the resulting `ClientConfig`.
+    pub fn build(self) -> ClientConfig {
+        self.config
+    }
+}
+
+/// Specifies how to hande redirects.
+///
+/// This is applied by all clients, i. e. `StubClient` follows the `Location` of stubbed
+/// redirect responses the same way `DirectClient` follows the ones of real servers.
+#[derive(Clone)]
 pub enum RedirectPolicy {
+    /// Follow redirects as long as fewer than the given number of requests were made in the
+    /// chain, returning an error otherwise (like `reqwest::redirect::Policy::limited`).
     Limit(usize),
+
+    /// Like `Limit`, but only redirects to the origin (scheme, host and port) of the initial
+    /// request are followed. A redirect to a different origin is returned as the response.
+    SameOrigin(usize),
+
+    /// Don't follow any redirects, returning the redirect responses.
     None,
+
+    /// Decide for every redirect using the provided function.
+    ///
+    /// Note that unlike `Limit` this will not protect you against redirect loops by itself.
+    Custom(Arc<dyn Fn(&RedirectAttempt) -> RedirectAction + Send + Sync>),
+}
+
+impl RedirectPolicy {
+    /// Create a `RedirectPolicy::Custom` from the provided function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reqwest_mock::config::RedirectPolicy;
+    ///
+    /// let policy = RedirectPolicy::custom(|attempt| {
+    ///     if attempt.previous().len() > 5 {
+    ///         attempt.error("too many redirects")
+    ///     } else if attempt.url().host_str() == Some("example.com") {
+    ///         // prevent redirects to example.com
+    ///         attempt.stop()
+    ///     } else {
+    ///         attempt.follow()
+    ///     }
+    /// });
+    /// ```
+    pub fn custom<F>(policy: F) -> Self
+    where
+        F: Fn(&RedirectAttempt) -> RedirectAction + Send + Sync + 'static,
+    {
+        RedirectPolicy::Custom(Arc::new(policy))
+    }
+
+    /// Determine the action to be taken for a redirect.
+    pub fn redirect(&self, attempt: &RedirectAttempt) -> RedirectAction {
+        match *self {
+            RedirectPolicy::Limit(max) => {
+                if attempt.previous().len() >= max {
+                    attempt.error("too many redirects")
+                } else {
+                    attempt.follow()
+                }
+            }
+            RedirectPolicy::SameOrigin(max) => {
+                let initial = attempt.previous().first();
+                if initial.map(Url::origin) != Some(attempt.url().origin()) {
+                    attempt.stop()
+                } else if attempt.previous().len() >= max {
+                    attempt.error("too many redirects")
+                } else {
+                    attempt.follow()
+                }
+            }
+            RedirectPolicy::None => attempt.stop(),
+            RedirectPolicy::Custom(ref policy) => policy(attempt),
+        }
+    }
 }
 
 impl Default for RedirectPolicy {
@@ -58,11 +335,179 @@ impl Default for RedirectPolicy {
     }
 }
 
+impl fmt::Debug for RedirectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RedirectPolicy::Limit(max) => f.debug_tuple("Limit").field(&max).finish(),
+            RedirectPolicy::SameOrigin(max) => f.debug_tuple("SameOrigin").field(&max).finish(),
+            RedirectPolicy::None => f.write_str("None"),
+            RedirectPolicy::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
 impl From<RedirectPolicy> for ::reqwest::redirect::Policy {
     fn from(p: RedirectPolicy) -> Self {
         match p {
             RedirectPolicy::Limit(n) => ::reqwest::redirect::Policy::limited(n),
             RedirectPolicy::None => ::reqwest::redirect::Policy::none(),
+            p => ::reqwest::redirect::Policy::custom(move |attempt| {
+                let action = p.redirect(&RedirectAttempt {
+                    status: attempt.status(),
+                    next: attempt.url(),
+                    previous: attempt.previous(),
+                });
+                match action {
+                    RedirectAction::Follow => attempt.follow(),
+                    RedirectAction::Stop => attempt.stop(),
+                    RedirectAction::Error(msg) => attempt.error(msg),
+                }
+            }),
+        }
+    }
+}
+
+/// Information about a redirect passed to a `RedirectPolicy`.
+#[derive(Debug)]
+pub struct RedirectAttempt<'a> {
+    status: StatusCode,
+    next: &'a Url,
+    previous: &'a [Url],
+}
+
+impl<'a> RedirectAttempt<'a> {
+    pub(crate) fn new(status: StatusCode, next: &'a Url, previous: &'a [Url]) -> Self {
+        RedirectAttempt {
+            status,
+            next,
+            previous,
         }
     }
+
+    /// The status code of the redirect response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The URL to be redirected to.
+    pub fn url(&self) -> &Url {
+        self.next
+    }
+
+    /// The URLs already requested in this chain, starting with the initial one.
+    pub fn previous(&self) -> &[Url] {
+        self.previous
+    }
+
+    /// Follow the redirect.
+    pub fn follow(&self) -> RedirectAction {
+        RedirectAction::Follow
+    }
+
+    /// Don't follow the redirect, returning the redirect response instead.
+    pub fn stop(&self) -> RedirectAction {
+        RedirectAction::Stop
+    }
+
+    /// Fail the request with an error.
+    pub fn error<S: Into<String>>(&self, msg: S) -> RedirectAction {
+        RedirectAction::Error(msg.into())
+    }
+}
+
+/// The action to be taken for a redirect, as determined by a `RedirectPolicy`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RedirectAction {
+    /// Follow the redirect.
+    Follow,
+
+    /// Return the redirect response.
+    Stop,
+
+    /// Fail the request with the provided message.
+    Error(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{ACCEPT, USER_AGENT};
+
+    #[test]
+    fn builder() {
+        let config = ClientConfig::builder()
+            .gzip(false)
+            .redirect(RedirectPolicy::None)
+            .connect_timeout(Duration::from_secs(2))
+            .timeout(Duration::from_secs(10))
+            .read_timeout(Duration::from_secs(3))
+            .base_url(Url::parse("https://example.com/api/").unwrap())
+            .build();
+
+        assert!(!config.gzip);
+        assert_eq!(config.connect_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(config.timeout, Some(Duration::from_secs(10)));
+        assert_eq!(config.read_timeout, Some(Duration::from_secs(3)));
+        assert_eq!(
+            config.base_url,
+            Some(Url::parse("https://example.com/api/").unwrap())
+        );
+    }
+
+    #[test]
+    fn default_request_headers() {
+        let config = ClientConfig::builder()
+            .default_header(ACCEPT, "application/json".parse().unwrap())
+            .default_header(USER_AGENT, "overridden".parse().unwrap())
+            .user_agent("MyClient".parse().unwrap())
+            .build();
+
+        let headers = config.default_request_headers();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[ACCEPT], "application/json");
+        assert_eq!(headers[USER_AGENT], "MyClient");
+    }
+
+    #[test]
+    fn redirect_policy() {
+        let a = Url::parse("http://a.example.com/1").unwrap();
+        let b = Url::parse("http://b.example.com/2").unwrap();
+        let a2 = Url::parse("http://a.example.com/3").unwrap();
+        let previous = [a.clone(), a2];
+
+        let attempt = RedirectAttempt::new(StatusCode::FOUND, &b, &previous[..1]);
+        assert_eq!(
+            RedirectPolicy::Limit(2).redirect(&attempt),
+            RedirectAction::Follow
+        );
+        assert_eq!(
+            RedirectPolicy::None.redirect(&attempt),
+            RedirectAction::Stop
+        );
+        assert_eq!(
+            RedirectPolicy::SameOrigin(5).redirect(&attempt),
+            RedirectAction::Stop
+        );
+
+        let attempt = RedirectAttempt::new(StatusCode::FOUND, &a, &previous);
+        assert_eq!(
+            RedirectPolicy::Limit(2).redirect(&attempt),
+            RedirectAction::Error("too many redirects".to_string())
+        );
+        assert_eq!(
+            RedirectPolicy::SameOrigin(5).redirect(&attempt),
+            RedirectAction::Follow
+        );
+
+        let custom = RedirectPolicy::custom(|attempt| {
+            if attempt.status() == StatusCode::MOVED_PERMANENTLY {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        });
+        assert_eq!(custom.redirect(&attempt), RedirectAction::Stop);
+        let attempt = RedirectAttempt::new(StatusCode::MOVED_PERMANENTLY, &a, &previous);
+        assert_eq!(custom.redirect(&attempt), RedirectAction::Follow);
+    }
 }
@@ -0,0 +1,389 @@
+//! Differences between requests, explaining why a stub or recording did not match.
+
+use body::BodyMem;
+use request::RequestMem;
+use reqwest::{Method, Url};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Bodies and JSON values longer than this many characters are shortened.
+const MAX_VALUE_LEN: usize = 200;
+
+/// The fields in which a request differs from the one it was expected to be.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequestDiff {
+    method: Option<Method>,
+    url: Option<Url>,
+    fields: Vec<FieldDiff>,
+}
+
+/// A single field in which a request differs from the one it was expected to be.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDiff {
+    /// The name of the field, one of `method`, `url` (without the query, or the whole URL if
+    /// only the order of its query differs), `query "<name>"`, `header "<name>"`, `body` or
+    /// `body <pointer>` for a value inside a JSON body, where `<pointer>` is a JSON pointer
+    /// like `/items/0`. A JSON body which only differs in its formatting or the order of its
+    /// keys is shown as a whole `body`.
+    pub field: String,
+
+    /// The expected value, none if the field was not expected to be present.
+    pub expected: Option<String>,
+
+    /// The actual value, none if the field is not present.
+    pub actual: Option<String>,
+}
+
+impl RequestDiff {
+    /// Compare every field of `actual` with the request it was `expected` to be.
+    pub(crate) fn between(expected: &RequestMem, actual: &RequestMem) -> Self {
+        let mut diff = RequestDiff::expecting(
+            Some(expected.header.method.clone()),
+            expected.header.url.clone(),
+        );
+        diff.method(&expected.header.method, &actual.header.method);
+        diff.url(&expected.header.url, &actual.header.url);
+        diff.headers(
+            &::helper::serialize_headers(&expected.header.headers),
+            &::helper::serialize_headers(&actual.header.headers),
+        );
+        diff.body(expected.body.as_ref(), actual.body.as_ref());
+        diff
+    }
+
+    /// Start comparing with a request expected to have `method`, any method if none, and `url`.
+    pub(crate) fn expecting(method: Option<Method>, url: Url) -> Self {
+        RequestDiff {
+            method,
+            url: Some(url),
+            fields: Vec::new(),
+        }
+    }
+
+    /// The method of the expected request, none if any method was expected.
+    pub fn expected_method(&self) -> Option<&Method> {
+        self.method.as_ref()
+    }
+
+    /// The URL of the expected request.
+    pub fn expected_url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
+    /// The fields which differ.
+    pub fn fields(&self) -> &[FieldDiff] {
+        &self.fields
+    }
+
+    /// Whether the requests are the same.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, field: String, expected: Option<String>, actual: Option<String>) {
+        self.fields.push(FieldDiff {
+            field,
+            expected,
+            actual,
+        });
+    }
+
+    /// Record that `actual` is not a body as `expected`, e. g. a multipart form with certain
+    /// parts.
+    pub(crate) fn body_mismatch(&mut self, expected: String, actual: Option<&BodyMem>) {
+        self.push(
+            "body".to_string(),
+            Some(expected),
+            actual.map(describe_body),
+        );
+    }
+
+    pub(crate) fn method(&mut self, expected: &Method, actual: &Method) {
+        if expected != actual {
+            self.push(
+                "method".to_string(),
+                Some(expected.to_string()),
+                Some(actual.to_string()),
+            );
+        }
+    }
+
+    /// Compare the URLs without their query, then each parameter of the query, or the whole
+    /// URLs if they only differ in the order of the parameters.
+    pub(crate) fn url(&mut self, expected: &Url, actual: &Url) {
+        let found = self.fields.len();
+        let without_query = |url: &Url| {
+            let mut url = url.clone();
+            url.set_query(None);
+            url
+        };
+        let (expected_base, actual_base) = (without_query(expected), without_query(actual));
+        if expected_base != actual_base {
+            self.push(
+                "url".to_string(),
+                Some(expected_base.to_string()),
+                Some(actual_base.to_string()),
+            );
+        }
+
+        let query = |url: &Url| {
+            let mut params = BTreeMap::<String, Vec<String>>::new();
+            for (name, value) in url.query_pairs() {
+                params
+                    .entry(name.into_owned())
+                    .or_default()
+                    .push(value.into_owned());
+            }
+            params
+                .into_iter()
+                .map(|(name, values)| (name, values.join(", ")))
+                .collect::<BTreeMap<_, _>>()
+        };
+        self.map("query", &query(expected), &query(actual));
+
+        if self.fields.len() == found && expected != actual {
+            self.push(
+                "url".to_string(),
+                Some(expected.to_string()),
+                Some(actual.to_string()),
+            );
+        }
+    }
+
+    pub(crate) fn headers(
+        &mut self,
+        expected: &BTreeMap<String, String>,
+        actual: &BTreeMap<String, String>,
+    ) {
+        self.map("header", expected, actual);
+    }
+
+    fn map(
+        &mut self,
+        kind: &str,
+        expected: &BTreeMap<String, String>,
+        actual: &BTreeMap<String, String>,
+    ) {
+        let names: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+        for name in names {
+            let (expected, actual) = (expected.get(name), actual.get(name));
+            if expected != actual {
+                self.push(
+                    format!("{} {:?}", kind, name),
+                    expected.cloned(),
+                    actual.cloned(),
+                );
+            }
+        }
+    }
+
+    /// Compare the bodies, value by value if both are JSON and their values differ.
+    pub(crate) fn body(&mut self, expected: Option<&BodyMem>, actual: Option<&BodyMem>) {
+        if expected == actual {
+            return;
+        }
+
+        let found = self.fields.len();
+        let json = |body: Option<&BodyMem>| {
+            body.and_then(BodyMem::bytes)
+                .and_then(|bytes| ::serde_json::from_slice::<Value>(bytes).ok())
+        };
+        if let (Some(expected), Some(actual)) = (json(expected), json(actual)) {
+            self.json("", Some(&expected), Some(&actual));
+        }
+        if self.fields.len() == found {
+            self.push(
+                "body".to_string(),
+                expected.map(describe_body),
+                actual.map(describe_body),
+            );
+        }
+    }
+
+    fn json(&mut self, pointer: &str, expected: Option<&Value>, actual: Option<&Value>) {
+        match (expected, actual) {
+            (Some(Value::Object(expected)), Some(Value::Object(actual))) => {
+                let keys: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+                for key in keys {
+                    let escaped = key.replace('~', "~0").replace('/', "~1");
+                    self.json(
+                        &format!("{}/{}", pointer, escaped),
+                        expected.get(key),
+                        actual.get(key),
+                    );
+                }
+            }
+            (Some(Value::Array(expected)), Some(Value::Array(actual))) => {
+                for i in 0..expected.len().max(actual.len()) {
+                    self.json(
+                        &format!("{}/{}", pointer, i),
+                        expected.get(i),
+                        actual.get(i),
+                    );
+                }
+            }
+            _ if expected == actual => {}
+            _ => {
+                let field = if pointer.is_empty() {
+                    "body".to_string()
+                } else {
+                    format!("body {}", pointer)
+                };
+                let show = |value: &Value| shorten(&value.to_string());
+                self.push(field, expected.map(show), actual.map(show));
+            }
+        }
+    }
+}
+
+/// Describe a body as its text, or its length if it is binary or was too large to keep.
+fn describe_body(body: &BodyMem) -> String {
+    match *body {
+        BodyMem::Bytes(ref bytes) => match ::std::str::from_utf8(bytes) {
+            Ok(text) => format!("{:?}", shorten(text)),
+            Err(_) => format!("<{} bytes>", bytes.len()),
+        },
+        BodyMem::Large(ref large) => {
+            format!("<{} bytes with SHA-256 {}>", large.length, large.sha256)
+        }
+    }
+}
+
+fn shorten(text: &str) -> String {
+    match text.char_indices().nth(MAX_VALUE_LEN) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |value: &Option<String>| match *value {
+            Some(ref value) => value.clone(),
+            None => "nothing".to_string(),
+        };
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.field,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// One line per differing field.
+impl fmt::Display for RequestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use request::RequestHeader;
+    use reqwest::header::{HeaderMap, ACCEPT};
+
+    fn request(method: Method, url: &str, accept: &str, body: &str) -> RequestMem {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, accept.parse().unwrap());
+        RequestMem {
+            header: RequestHeader {
+                url: Url::parse(url).unwrap(),
+                method,
+                headers,
+            },
+            body: Some(BodyMem::Bytes(body.as_bytes().to_vec())),
+        }
+    }
+
+    #[test]
+    fn fields() {
+        let expected = request(
+            Method::GET,
+            "http://example.com/items?page=1&sort=name",
+            "application/json",
+            "plain",
+        );
+        let actual = request(
+            Method::POST,
+            "http://example.com/items?page=2",
+            "text/plain",
+            "other",
+        );
+        let diff = RequestDiff::between(&expected, &actual);
+        assert_eq!(
+            diff.to_string(),
+            "method: expected GET, got POST\n\
+             query \"page\": expected 1, got 2\n\
+             query \"sort\": expected name, got nothing\n\
+             header \"accept\": expected application/json, got text/plain\n\
+             body: expected \"plain\", got \"other\""
+        );
+        assert_eq!(diff.expected_method(), Some(&Method::GET));
+        assert_eq!(diff.expected_url(), Some(&expected.header.url));
+        assert!(RequestDiff::between(&expected, &expected).is_empty());
+    }
+
+    #[test]
+    fn json_body() {
+        let expected = request(
+            Method::POST,
+            "http://example.com/",
+            "*/*",
+            r#"{"name": "a", "tags": ["x", "y"], "a/b": 1}"#,
+        );
+        let actual = request(
+            Method::POST,
+            "http://example.com/",
+            "*/*",
+            r#"{"name": "b", "tags": ["x"], "a/b": 1, "new": null}"#,
+        );
+        let diff = RequestDiff::between(&expected, &actual);
+        assert_eq!(
+            diff.to_string(),
+            "body /name: expected \"a\", got \"b\"\n\
+             body /new: expected nothing, got null\n\
+             body /tags/1: expected \"y\", got nothing"
+        );
+    }
+
+    #[test]
+    fn query_order() {
+        let expected = request(Method::GET, "http://example.com/?a=1&b=2", "*/*", "");
+        let actual = request(Method::GET, "http://example.com/?b=2&a=1", "*/*", "");
+        assert_eq!(
+            RequestDiff::between(&expected, &actual).to_string(),
+            "url: expected http://example.com/?a=1&b=2, got http://example.com/?b=2&a=1"
+        );
+    }
+
+    #[test]
+    fn json_formatting() {
+        let expected = request(
+            Method::POST,
+            "http://example.com/",
+            "*/*",
+            r#"{"a":1,"b":2}"#,
+        );
+        let actual = request(
+            Method::POST,
+            "http://example.com/",
+            "*/*",
+            r#"{"b": 2, "a": 1}"#,
+        );
+        assert_eq!(
+            RequestDiff::between(&expected, &actual).to_string(),
+            "body: expected \"{\\\"a\\\":1,\\\"b\\\":2}\", \
+             got \"{\\\"b\\\": 2, \\\"a\\\": 1}\""
+        );
+    }
+}
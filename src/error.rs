@@ -1,20 +1,368 @@
-//! Defines the `Error` type we use in this library (error-chain).
+//! Defines the `Error` type we use in this library.
 
-error_chain! {
-    types {
-        Error, ErrorKind, ResultExt;
+use diff::RequestDiff;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode, Url};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// The error type of this crate.
+///
+/// Use `kind` to find out what went wrong, and `source` for the underlying error, if any.
+/// It is `Send` and `Sync`, so it can be turned into a `Box<dyn std::error::Error + Send +
+/// Sync>`.
+pub struct Error {
+    inner: Box<Inner>,
+}
+
+struct Inner {
+    kind: ErrorKind,
+    source: Option<Box<dyn StdError + Send + Sync>>,
+}
+
+/// The kinds of errors which can occur.
+// `Error` boxes its kind, so the size of the largest variant doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// `StubClient` has no stub for the request and its `StubDefault` is `Error`.
+    NoStubMatched {
+        method: Method,
+        url: Url,
+        headers: HeaderMap,
+        /// The body, unless the request had none or it was larger than
+        /// `ClientConfig::max_buffered_body`.
+        body: Option<Vec<u8>>,
+        /// How the request differs from the registered stubs closest to it, closest first.
+        closest: Vec<RequestDiff>,
+    },
+
+    /// `ReplayClient` has no recording of the request in this file, and may not record it.
+    ReplayMissing(PathBuf),
+
+    /// The request differs from the one recorded in this file, and `ReplayClient` may not
+    /// record it again.
+    ReplayChanged { path: PathBuf, diff: RequestDiff },
+
+    /// The request was made more often than it was recorded in this file, and the
+    /// `Exhausted` setting of `ReplayClient` doesn't allow replaying or recording it again.
+    ReplayExhausted {
+        path: PathBuf,
+        /// The number of responses recorded.
+        recorded: usize,
+    },
+
+    /// A replay file has a format version this version of the crate can't read. Files too
+    /// old to be migrated are only an error if `ReplayClient` may not record them again,
+    /// files of a newer version always are.
+    ReplayFormatMismatch {
+        path: PathBuf,
+        /// The format version of the file, none if it has none.
+        found: Option<u64>,
+        expected: u64,
+    },
+
+    /// A replay file path is not a relative path inside of the directory of the
+    /// `RecordingTarget`, or is the path of its index.
+    InvalidReplayPath(PathBuf),
+
+    /// The unused replay files in this directory were requested, but no usage of them was
+    /// recorded with `ReplayClient::track_usage`.
+    NoUsageRecorded(PathBuf),
+
+    /// A stub could not be registered with `StubClient`, the source is the
+    /// `RegisterStubError`.
+    RegisterStub,
+
+    /// A URL could not be parsed or resolved, or is not a valid URI.
+    InvalidUrl(String),
+
+    /// The request could not be built, e. g. because of an invalid header or a body which
+    /// could not be serialized.
+    InvalidRequest,
+
+    /// The request or reading the response timed out.
+    Timeout,
+
+    /// The connection to the server could not be established.
+    Connect,
+
+    /// The server responded with a client (4xx) or server (5xx) error status, returned by
+    /// `error_for_status`. Carries the status and body of the response.
+    Status(StatusCode, Vec<u8>),
+
+    /// A redirect was refused by the `RedirectPolicy` or can't be followed.
+    Redirect(String),
+
+    /// Any other error performing the request.
+    Request,
+
+    /// Reading or writing a file or body failed.
+    Io,
+
+    /// The response body or a replay file could not be decoded.
+    Decode,
+}
+
+impl Error {
+    /// Create an error of `kind` without a source.
+    pub fn new(kind: ErrorKind) -> Self {
+        Error {
+            inner: Box::new(Inner { kind, source: None }),
+        }
+    }
+
+    /// Create an error of `kind` caused by `source`.
+    pub fn with_source<E>(kind: ErrorKind, source: E) -> Self
+    where
+        E: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        Error {
+            inner: Box::new(Inner {
+                kind,
+                source: Some(source.into()),
+            }),
+        }
+    }
+
+    /// Create an `InvalidUrl` error for `url`, caused by `source`.
+    pub(crate) fn invalid_url<E>(url: &str, source: E) -> Self
+    where
+        E: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        Error::with_source(ErrorKind::InvalidUrl(url.to_string()), source)
+    }
+
+    /// What went wrong.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+
+    /// Turn the error into its kind, dropping the source.
+    pub fn into_kind(self) -> ErrorKind {
+        self.inner.kind
+    }
+
+    /// Whether the request or reading the response timed out.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self.inner.kind, ErrorKind::Timeout)
+    }
+
+    /// Whether the connection to the server could not be established.
+    pub fn is_connect(&self) -> bool {
+        matches!(self.inner.kind, ErrorKind::Connect)
     }
 
-    links {
+    /// The status of the response, if this error was returned by `error_for_status`.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self.inner.kind {
+            ErrorKind::Status(status, _) => Some(status),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner.kind {
+            ErrorKind::NoStubMatched {
+                ref method,
+                ref url,
+                ref closest,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Requested {} {}, without having provided a stub for it.",
+                    method, url
+                )?;
+                for (i, diff) in closest.iter().enumerate() {
+                    write!(f, "\nClosest stub #{} (", i + 1)?;
+                    if let Some(method) = diff.expected_method() {
+                        write!(f, "{} ", method)?;
+                    }
+                    if let Some(url) = diff.expected_url() {
+                        write!(f, "{}", url)?;
+                    }
+                    write!(f, "):")?;
+                    for field in diff.fields() {
+                        write!(f, "\n    {}", field)?;
+                    }
+                }
+            }
+            ErrorKind::ReplayMissing(ref path) => {
+                write!(f, "no recording of the request in {}", path.display())?
+            }
+            ErrorKind::ReplayChanged { ref path, ref diff } => {
+                write!(
+                    f,
+                    "the request differs from the one recorded in {}:",
+                    path.display()
+                )?;
+                for field in diff.fields() {
+                    write!(f, "\n    {}", field)?;
+                }
+            }
+            ErrorKind::ReplayExhausted { ref path, recorded } => write!(
+                f,
+                "all {} responses recorded in {} were replayed already",
+                recorded,
+                path.display()
+            )?,
+            ErrorKind::ReplayFormatMismatch {
+                ref path,
+                found,
+                expected,
+            } => {
+                write!(f, "replay file {} has format version ", path.display())?;
+                match found {
+                    Some(found) => write!(f, "{}", found)?,
+                    None => write!(f, "none")?,
+                }
+                write!(f, ", expected {}", expected)?;
+                if found.is_some_and(|found| found > expected) {
+                    write!(f, ", it was written by a newer version of reqwest_mock")?
+                }
+            }
+            ErrorKind::InvalidReplayPath(ref path) => {
+                write!(f, "invalid replay file path {}", path.display())?
+            }
+            ErrorKind::NoUsageRecorded(ref path) => write!(
+                f,
+                "no usage of the replay files in {} was recorded, enable \
+                 `ReplayClient::track_usage` and run the tests first",
+                path.display()
+            )?,
+            ErrorKind::RegisterStub => write!(f, "registering stub failed")?,
+            ErrorKind::InvalidUrl(ref url) => write!(f, "invalid url: {}", url)?,
+            ErrorKind::InvalidRequest => write!(f, "invalid request")?,
+            ErrorKind::Timeout => write!(f, "timed out")?,
+            ErrorKind::Connect => write!(f, "connection failed")?,
+            ErrorKind::Status(status, _) => write!(f, "HTTP status {}", status)?,
+            ErrorKind::Redirect(ref msg) => write!(f, "redirect failed: {}", msg)?,
+            ErrorKind::Request => write!(f, "request failed")?,
+            ErrorKind::Io => write!(f, "I/O error")?,
+            ErrorKind::Decode => write!(f, "decoding failed")?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Error")
+            .field("kind", &self.inner.kind)
+            .field("source", &self.inner.source)
+            .finish()
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.inner
+            .source
+            .as_ref()
+            .map(|source| &**source as &(dyn StdError + 'static))
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        // Timeouts reading a streamed body surface as I/O errors, reqwest wraps its own.
+        let reqwest_timeout = e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<::reqwest::Error>())
+            .is_some_and(::reqwest::Error::is_timeout);
+        let kind = match e.kind() {
+            io::ErrorKind::TimedOut => ErrorKind::Timeout,
+            _ if reqwest_timeout => ErrorKind::Timeout,
+            _ => ErrorKind::Io,
+        };
+        Error::with_source(kind, e)
+    }
+}
+
+impl From<::client::RegisterStubError> for Error {
+    fn from(e: ::client::RegisterStubError) -> Self {
+        Error::with_source(ErrorKind::RegisterStub, e)
+    }
+}
+
+impl From<::reqwest::Error> for Error {
+    fn from(e: ::reqwest::Error) -> Self {
+        let kind = if e.is_timeout() {
+            ErrorKind::Timeout
+        } else if e.is_connect() {
+            ErrorKind::Connect
+        } else if e.is_builder() {
+            ErrorKind::InvalidRequest
+        } else {
+            ErrorKind::Request
+        };
+        Error::with_source(kind, e)
+    }
+}
+
+impl From<::http::Error> for Error {
+    fn from(e: ::http::Error) -> Self {
+        Error::with_source(ErrorKind::InvalidRequest, e)
+    }
+}
+
+impl From<::serde_json::Error> for Error {
+    fn from(e: ::serde_json::Error) -> Self {
+        Error::with_source(ErrorKind::Decode, e)
+    }
+}
+
+impl From<::std::string::FromUtf8Error> for Error {
+    fn from(e: ::std::string::FromUtf8Error) -> Self {
+        Error::with_source(ErrorKind::Decode, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn send_sync() {
+        assert_send_sync::<Error>();
     }
 
-    foreign_links {
-        Io(::std::io::Error);
-        Reqwest(::reqwest::Error);
-        SerdeJson(::serde_json::Error);
-        FromUtf8(::std::string::FromUtf8Error);
+    #[test]
+    fn io_timeout() {
+        let error = Error::from(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+        assert!(error.is_timeout());
+        assert_eq!(error.to_string(), "timed out");
+        assert_eq!(error.source().unwrap().to_string(), "slow");
     }
 
-    errors {
+    #[test]
+    fn display() {
+        let error = Error::from(ErrorKind::ReplayFormatMismatch {
+            path: PathBuf::from("a.json"),
+            found: Some(2),
+            expected: 3,
+        });
+        assert_eq!(
+            error.to_string(),
+            "replay file a.json has format version 2, expected 3"
+        );
+        assert_eq!(
+            Error::from(ErrorKind::InvalidReplayPath(PathBuf::from("../a.json"))).to_string(),
+            "invalid replay file path ../a.json"
+        );
     }
 }
@@ -1,9 +1,17 @@
 //! Defines some things used from different modules but not to be exported.
 
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::Value;
 use std::collections::BTreeMap;
 use std::iter::FromIterator;
 
+/// The field of a stored body which is valid UTF-8, stored as a string.
+pub const F_BODY: &str = "body";
+/// The field of a stored body which is a JSON object or array, embedded as JSON value.
+pub const F_BODY_JSON: &str = "body_json";
+/// The field of a stored binary body, stored base64 encoded.
+pub const F_BODY_BASE64: &str = "body_base64";
+
 pub fn serialize_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
     let tuples_iter = headers
         .iter()
@@ -24,6 +32,38 @@ pub fn deserialize_headers(map: &BTreeMap<String, String>) -> HeaderMap {
     headers
 }
 
+/// Return the field a body is stored in and its value, so it is as readable as possible.
+///
+/// JSON is only embedded if serializing it again results in exactly the same body.
+pub fn store_body(body: &[u8]) -> (&'static str, Value) {
+    if let Ok(value) = ::serde_json::from_slice::<Value>(body) {
+        if (value.is_object() || value.is_array())
+            && ::serde_json::to_vec(&value).ok().as_deref() == Some(body)
+        {
+            return (F_BODY_JSON, value);
+        }
+    }
+    match ::std::str::from_utf8(body) {
+        // Control characters other than line breaks and tabs indicate binary data.
+        Ok(text) if text.chars().all(|c| !c.is_control() || c.is_whitespace()) => {
+            (F_BODY, Value::String(text.to_string()))
+        }
+        _ => (F_BODY_BASE64, Value::String(::base64::encode(body))),
+    }
+}
+
+/// Restore a body stored in `field` by `store_body`.
+pub fn load_body(field: &str, value: Value) -> Result<Vec<u8>, String> {
+    match (field, value) {
+        (F_BODY_JSON, value) => ::serde_json::to_vec(&value).map_err(|e| e.to_string()),
+        (F_BODY, Value::String(text)) => Ok(text.into_bytes()),
+        (F_BODY_BASE64, Value::String(encoded)) => {
+            ::base64::decode(&encoded).map_err(|e| e.to_string())
+        }
+        (field, value) => Err(format!("invalid value of {}: {}", field, value)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -57,4 +97,22 @@ mod tests {
 
         assert_eq!(ser1, ser2);
     }
+
+    #[test]
+    fn store_body() {
+        let cases: &[(&[u8], &str)] = &[
+            (br#"{"b":1,"a":[true,null]}"#, F_BODY_JSON),
+            (br#"{"a": 1}"#, F_BODY),
+            (b"42", F_BODY),
+            (b"plain text", F_BODY),
+            (b"line\r\n\ttab", F_BODY),
+            (&[0xff, 0x00, 0x10], F_BODY_BASE64),
+            (&[0x02, 0x04], F_BODY_BASE64),
+        ];
+        for &(body, field) in cases {
+            let (stored_field, value) = super::store_body(body);
+            assert_eq!(stored_field, field);
+            assert_eq!(load_body(stored_field, value).unwrap(), body);
+        }
+    }
 }
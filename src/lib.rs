@@ -45,35 +45,50 @@
 //! ```
 
 extern crate base64;
-#[macro_use]
-extern crate error_chain;
+extern crate bytes;
+extern crate encoding_rs;
+extern crate fs2;
 extern crate http;
+extern crate http_body;
 #[macro_use]
 extern crate log;
+extern crate mime;
 extern crate reqwest;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate serde_urlencoded;
+extern crate sha2;
+extern crate tempfile;
+extern crate tokio;
+extern crate tower_service;
 extern crate twox_hash;
 extern crate url;
 
 mod helper;
 
 pub mod config;
+pub mod diff;
 pub mod error;
 
 mod body;
 pub use body::Body;
 
-mod request;
+pub mod multipart;
+
+mod redirect;
+pub mod request;
 mod response;
 
+pub mod blocking;
 pub mod client;
 mod request_builder;
 
 pub use self::client::*;
 pub use self::error::Error;
+pub use self::request::Request;
+pub use self::response::{Response, StreamingResponse};
 
-pub use reqwest::{header, IntoUrl, Method, StatusCode, Url};
+pub use reqwest::{header, tls, Certificate, Identity, IntoUrl, Method, StatusCode, Url};
 pub use url::ParseError as UrlError;
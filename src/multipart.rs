@@ -0,0 +1,606 @@
+//! `multipart/form-data` request bodies.
+//!
+//! Unlike the forms of reqwest, a `Form` is serialized deterministically, using a fixed
+//! boundary unless a different one is provided. That way the same form always results in the
+//! same request body, which `ReplayClient` and `StubClient` rely on to recognize requests.
+//!
+//! # Examples
+//!
+//! ```
+//! use reqwest_mock::multipart::{Form, Part};
+//! use reqwest_mock::{Client, DirectClient};
+//!
+//! let form = Form::new()
+//!     .text("title", "Holiday")
+//!     .part(
+//!         "photo",
+//!         Part::bytes(vec![0xff, 0xd8, 0xff])
+//!             .file_name("beach.jpg")
+//!             .mime_str("image/jpeg")
+//!             .unwrap(),
+//!     );
+//!
+//! let client = DirectClient::new();
+//! let request = client.post("https://example.com/upload").multipart(form);
+//! ```
+
+use reqwest::header::HeaderValue;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+/// The boundary used by `Form::new`.
+pub const DEFAULT_BOUNDARY: &str = "reqwest-mock-boundary-d3a51c6e9b7f4208";
+
+/// A `multipart/form-data` request body.
+#[derive(Debug)]
+pub struct Form {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+/// A single field of a `Form`.
+#[derive(Debug)]
+pub struct Part {
+    value: PartValue,
+    file_name: Option<String>,
+    mime: Option<String>,
+}
+
+#[derive(Debug)]
+enum PartValue {
+    Bytes(Vec<u8>),
+    File(File),
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Form {
+    /// Create an empty form using `DEFAULT_BOUNDARY`.
+    pub fn new() -> Self {
+        Form {
+            boundary: DEFAULT_BOUNDARY.to_string(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Create an empty form using a custom boundary.
+    ///
+    /// This is only needed if the contents of the form could contain `DEFAULT_BOUNDARY`.
+    ///
+    /// Returns an error unless `boundary` consists of 1 to 70 ASCII letters, digits and the
+    /// characters `'+-._`, which can be used in the `Content-Type` header without quoting.
+    pub fn with_boundary<S: Into<String>>(boundary: S) -> Result<Self, ::error::Error> {
+        let boundary = boundary.into();
+        let valid = !boundary.is_empty()
+            && boundary.len() <= 70
+            && boundary
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "'+-._".contains(c));
+        if !valid {
+            return Err(::error::Error::with_source(
+                ::error::ErrorKind::InvalidRequest,
+                format!("invalid multipart boundary: {:?}", boundary),
+            ));
+        }
+        Ok(Form {
+            boundary,
+            parts: Vec::new(),
+        })
+    }
+
+    /// The boundary separating the parts of the form.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Add a text field.
+    pub fn text<N, V>(self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.part(name, Part::text(value))
+    }
+
+    /// Add a file field, using the file name of the path.
+    pub fn file<N, P>(self, name: N, path: P) -> io::Result<Self>
+    where
+        N: Into<String>,
+        P: AsRef<Path>,
+    {
+        Ok(self.part(name, Part::file(path)?))
+    }
+
+    /// Add a custom field.
+    pub fn part<N: Into<String>>(mut self, name: N, part: Part) -> Self {
+        self.parts.push((name.into(), part));
+        self
+    }
+
+    /// The value of the `Content-Type` header of a request with this form as body.
+    pub(crate) fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("multipart/form-data; boundary={}", self.boundary))
+            .expect("the boundary is checked by `with_boundary`")
+    }
+
+    /// The serialized length of the form, if the size of all files can be determined.
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        let mut length = self.closing_delimiter().len() as u64;
+        for (name, part) in &self.parts {
+            length += self.part_head(name, part).len() as u64 + 2;
+            length += match part.value {
+                PartValue::Bytes(ref bytes) => bytes.len() as u64,
+                PartValue::File(ref file) => file.metadata().ok()?.len(),
+            };
+        }
+        Some(length)
+    }
+
+    /// Return a reader producing the serialized form, without reading the files up front.
+    pub(crate) fn into_reader(self) -> FormReader {
+        let heads: Vec<Vec<u8>> = self
+            .parts
+            .iter()
+            .map(|(name, part)| self.part_head(name, part))
+            .collect();
+        let closing = self.closing_delimiter();
+
+        let mut readers: VecDeque<Box<dyn Read + Send>> = VecDeque::new();
+        for (head, (_, part)) in heads.into_iter().zip(self.parts) {
+            readers.push_back(Box::new(Cursor::new(head)));
+            readers.push_back(match part.value {
+                PartValue::Bytes(bytes) => Box::new(Cursor::new(bytes)),
+                PartValue::File(file) => Box::new(file),
+            });
+            readers.push_back(Box::new(&b"\r\n"[..]));
+        }
+        readers.push_back(Box::new(Cursor::new(closing)));
+
+        FormReader { readers }
+    }
+
+    /// Serialize the form, reading all files.
+    pub(crate) fn try_to_vec(self) -> Result<Vec<u8>, io::Error> {
+        let mut bytes = Vec::new();
+        self.into_reader().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn part_head(&self, name: &str, part: &Part) -> Vec<u8> {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape_quoted(name)
+        );
+        if let Some(ref file_name) = part.file_name {
+            head.push_str(&format!("; filename=\"{}\"", escape_quoted(file_name)));
+        }
+        head.push_str("\r\n");
+        if let Some(ref mime) = part.mime {
+            head.push_str(&format!("Content-Type: {}\r\n", mime));
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+
+    fn closing_delimiter(&self) -> Vec<u8> {
+        format!("--{}--\r\n", self.boundary).into_bytes()
+    }
+}
+
+impl Part {
+    /// Create a part containing text.
+    pub fn text<V: Into<String>>(value: V) -> Self {
+        Self::bytes(value.into().into_bytes())
+    }
+
+    /// Create a part containing binary data.
+    pub fn bytes<V: Into<Vec<u8>>>(value: V) -> Self {
+        Part {
+            value: PartValue::Bytes(value.into()),
+            file_name: None,
+            mime: None,
+        }
+    }
+
+    /// Create a part streaming the contents of a file, using the file name of the path.
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        Ok(Part {
+            value: PartValue::File(File::open(path)?),
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            mime: None,
+        })
+    }
+
+    /// Set the file name of the part.
+    pub fn file_name<S: Into<String>>(mut self, file_name: S) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Set the content type of the part.
+    ///
+    /// Returns an error if `mime` is not a valid header value.
+    pub fn mime_str(mut self, mime: &str) -> Result<Self, ::error::Error> {
+        if HeaderValue::from_str(mime).is_err() {
+            return Err(::error::Error::with_source(
+                ::error::ErrorKind::InvalidRequest,
+                format!("invalid content type: {}", mime),
+            ));
+        }
+        self.mime = Some(mime.to_string());
+        Ok(self)
+    }
+}
+
+/// Matches a single part of a `multipart/form-data` request body.
+///
+/// Use with [RequestStubber::part](../client/struct.RequestStubber.html#method.part) to stub
+/// requests by some of their parts only. Everything not specified is not checked.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PartMatcher {
+    name: String,
+    data: Option<Vec<u8>>,
+    file_name: Option<String>,
+    mime: Option<String>,
+}
+
+impl PartMatcher {
+    /// Match a part with the provided name.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        PartMatcher {
+            name: name.into(),
+            data: None,
+            file_name: None,
+            mime: None,
+        }
+    }
+
+    /// Only match parts containing this text.
+    pub fn text<V: Into<String>>(self, value: V) -> Self {
+        self.bytes(value.into().into_bytes())
+    }
+
+    /// Only match parts containing this data.
+    pub fn bytes<V: Into<Vec<u8>>>(mut self, value: V) -> Self {
+        self.data = Some(value.into());
+        self
+    }
+
+    /// Only match parts with this file name.
+    pub fn file_name<S: Into<String>>(mut self, file_name: S) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Only match parts with this content type.
+    pub fn mime_str<S: Into<String>>(mut self, mime: S) -> Self {
+        self.mime = Some(mime.into());
+        self
+    }
+
+    pub(crate) fn matches(&self, part: &ParsedPart) -> bool {
+        self.name == part.name
+            && self.data.as_ref().map_or(true, |data| *data == part.data)
+            && self
+                .file_name
+                .as_ref()
+                .map_or(true, |name| Some(name) == part.file_name.as_ref())
+            && self
+                .mime
+                .as_ref()
+                .map_or(true, |mime| Some(mime) == part.mime.as_ref())
+    }
+}
+
+/// A part of a serialized form.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParsedPart {
+    pub name: String,
+    pub file_name: Option<String>,
+    pub mime: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Extract the boundary from the value of a `multipart/form-data` `Content-Type` header.
+pub(crate) fn boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| {
+            let mut kv = param.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) if key.eq_ignore_ascii_case("boundary") => {
+                    Some(value.trim_matches('"'))
+                }
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Parse a serialized form, returning `None` if it is malformed.
+pub(crate) fn parse(body: &[u8], boundary: &str) -> Option<Vec<ParsedPart>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let separator = [&b"\r\n"[..], &delimiter].concat();
+
+    if !body.starts_with(&delimiter) {
+        return None;
+    }
+    let mut rest = &body[delimiter.len()..];
+    let mut parts = Vec::new();
+
+    while !rest.starts_with(b"--") {
+        rest = strip_prefix(rest, b"\r\n")?;
+        let head_end = find(rest, b"\r\n\r\n")?;
+        let head = ::std::str::from_utf8(&rest[..head_end]).ok()?;
+        rest = &rest[head_end + 4..];
+        let data_end = find(rest, &separator)?;
+
+        let mut part = ParsedPart {
+            name: String::new(),
+            file_name: None,
+            mime: None,
+            data: rest[..data_end].to_vec(),
+        };
+        let mut name = None;
+        for line in head.split("\r\n") {
+            let mut kv = line.splitn(2, ':');
+            let (key, value) = (kv.next()?.trim(), kv.next()?.trim());
+            if key.eq_ignore_ascii_case("content-disposition") {
+                for (key, value) in disposition_params(value)? {
+                    match key {
+                        "name" => name = Some(value),
+                        "filename" => part.file_name = Some(value),
+                        _ => {}
+                    }
+                }
+            } else if key.eq_ignore_ascii_case("content-type") {
+                part.mime = Some(value.to_string());
+            }
+        }
+        part.name = name?;
+        parts.push(part);
+
+        rest = &rest[data_end + separator.len()..];
+    }
+
+    Some(parts)
+}
+
+/// The parameters of the value of a `Content-Disposition` header, whose quoted strings may
+/// contain `;` and characters escaped with `\`. Returns `None` if it is malformed.
+fn disposition_params(value: &str) -> Option<Vec<(&str, String)>> {
+    let mut params = Vec::new();
+    let mut rest = match value.find(';') {
+        Some(start) => &value[start..],
+        None => return Some(params),
+    };
+    while !rest.is_empty() {
+        rest = rest.strip_prefix(';')?.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim();
+        rest = rest[eq + 1..].trim_start();
+
+        if rest.starts_with('"') {
+            let mut quoted = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            let end = loop {
+                match chars.next()? {
+                    (end, '"') => break end,
+                    (_, '\\') => quoted.push(chars.next()?.1),
+                    (_, c) => quoted.push(c),
+                }
+            };
+            params.push((key, unescape_quoted(&quoted)));
+            rest = rest[end + 1..].trim_start();
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            params.push((key, rest[..end].trim().to_string()));
+            rest = &rest[end..];
+        }
+    }
+    Some(params)
+}
+
+/// Reads a serialized `Form`, see `Form::into_reader`.
+pub(crate) struct FormReader {
+    readers: VecDeque<Box<dyn Read + Send>>,
+}
+
+impl Read for FormReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(reader) = self.readers.front_mut() {
+            match reader.read(buf)? {
+                0 if !buf.is_empty() => {}
+                n => return Ok(n),
+            }
+            self.readers.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+fn escape_quoted(s: &str) -> Cow<'_, str> {
+    if s.contains(['"', '\r', '\n']) {
+        Cow::Owned(
+            s.replace('"', "%22")
+                .replace('\r', "%0D")
+                .replace('\n', "%0A"),
+        )
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+fn unescape_quoted(s: &str) -> String {
+    s.replace("%22", "\"")
+        .replace("%0D", "\r")
+        .replace("%0A", "\n")
+}
+
+fn strip_prefix<'a>(s: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
+    if s.starts_with(prefix) {
+        Some(&s[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_form() -> Form {
+        Form::new().text("title", "Holiday").part(
+            "photo",
+            Part::bytes(vec![1, 2, 3])
+                .file_name("beach \"1\".jpg")
+                .mime_str("image/jpeg")
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn serialize() {
+        let form = example_form();
+        let length = form.content_length();
+        let bytes = form.try_to_vec().unwrap();
+
+        let mut expected = format!(
+            "--{b}\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             Holiday\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"photo\"; filename=\"beach %221%22.jpg\"\r\n\
+             Content-Type: image/jpeg\r\n\
+             \r\n",
+            b = DEFAULT_BOUNDARY
+        )
+        .into_bytes();
+        expected.extend_from_slice(&[1, 2, 3]);
+        expected.extend_from_slice(format!("\r\n--{}--\r\n", DEFAULT_BOUNDARY).as_bytes());
+
+        assert_eq!(bytes, expected);
+        assert_eq!(length, Some(expected.len() as u64));
+
+        // Serializing the same form again results in the same bytes.
+        assert_eq!(example_form().try_to_vec().unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_serialized() {
+        let bytes = example_form().try_to_vec().unwrap();
+        let parts = parse(&bytes, DEFAULT_BOUNDARY).unwrap();
+
+        assert_eq!(
+            parts,
+            vec![
+                ParsedPart {
+                    name: "title".to_string(),
+                    file_name: None,
+                    mime: None,
+                    data: b"Holiday".to_vec(),
+                },
+                ParsedPart {
+                    name: "photo".to_string(),
+                    file_name: Some("beach \"1\".jpg".to_string()),
+                    mime: Some("image/jpeg".to_string()),
+                    data: vec![1, 2, 3],
+                },
+            ]
+        );
+
+        assert!(parse(b"garbage", DEFAULT_BOUNDARY).is_none());
+        assert_eq!(parse(&bytes, "other"), None);
+    }
+
+    #[test]
+    fn parse_quoted_params() {
+        let form = Form::new()
+            .text("x;y \"z\"", "1")
+            .part("photo", Part::bytes(vec![2]).file_name("a;b \"c\".jpg"));
+        let bytes = form.try_to_vec().unwrap();
+        let parts = parse(&bytes, DEFAULT_BOUNDARY).unwrap();
+        assert_eq!(parts[0].name, "x;y \"z\"");
+        assert_eq!(parts[1].name, "photo");
+        assert_eq!(parts[1].file_name.as_deref(), Some("a;b \"c\".jpg"));
+
+        // Other clients escape quotes with a backslash.
+        let bytes = b"--b\r\n\
+            Content-Disposition: form-data; filename=\"a\\\";b\" ; name=\"f\"\r\n\r\n\
+            1\r\n--b--\r\n";
+        let parts = parse(bytes, "b").unwrap();
+        assert_eq!(parts[0].name, "f");
+        assert_eq!(parts[0].file_name.as_deref(), Some("a\";b"));
+
+        let unterminated = b"--b\r\nContent-Disposition: form-data; name=\"f\r\n\r\n1\r\n--b--\r\n";
+        assert_eq!(parse(unterminated, "b"), None);
+    }
+
+    #[test]
+    fn content_type_boundary() {
+        let content_type = example_form().content_type();
+        assert_eq!(
+            boundary(content_type.to_str().unwrap()),
+            Some(DEFAULT_BOUNDARY)
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; boundary=\"abc\""),
+            Some("abc")
+        );
+        assert_eq!(boundary("application/json"), None);
+    }
+
+    #[test]
+    fn custom_boundary() {
+        let form = Form::with_boundary("my-boundary_1").unwrap();
+        assert_eq!(
+            form.content_type(),
+            "multipart/form-data; boundary=my-boundary_1"
+        );
+        assert!(Form::with_boundary("").is_err());
+        assert!(Form::with_boundary("a\r\nb").is_err());
+        assert!(Form::with_boundary("a;b").is_err());
+        assert!(Form::with_boundary("a".repeat(71)).is_err());
+    }
+
+    #[test]
+    fn part_matcher() {
+        let bytes = example_form().try_to_vec().unwrap();
+        let parts = parse(&bytes, DEFAULT_BOUNDARY).unwrap();
+
+        assert!(PartMatcher::new("title").matches(&parts[0]));
+        assert!(PartMatcher::new("title").text("Holiday").matches(&parts[0]));
+        assert!(!PartMatcher::new("title").text("Work").matches(&parts[0]));
+        assert!(PartMatcher::new("photo")
+            .mime_str("image/jpeg")
+            .file_name("beach \"1\".jpg")
+            .matches(&parts[1]));
+        assert!(!PartMatcher::new("photo")
+            .mime_str("image/png")
+            .matches(&parts[1]));
+    }
+}
@@ -0,0 +1,147 @@
+//! Following of redirects for the clients which can't leave it to reqwest, mirroring what
+//! reqwest does internally.
+
+use config::{ClientConfig, RedirectAction, RedirectAttempt};
+use error::{Error, ErrorKind};
+use request::RequestMem;
+use reqwest::header::{
+    HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE,
+    LOCATION, PROXY_AUTHORIZATION, REFERER, TRANSFER_ENCODING, WWW_AUTHENTICATE,
+};
+use reqwest::{Method, StatusCode, Url};
+use response::{Response, StreamingResponse};
+
+/// The parts of a response needed to decide whether it is a redirect.
+pub trait ResponseHead {
+    fn status(&self) -> StatusCode;
+    fn headers(&self) -> &HeaderMap;
+}
+
+impl ResponseHead for Response {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+impl ResponseHead for StreamingResponse {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+/// Perform `request` by calling `send` for it and every redirect followed according to the
+/// `RedirectPolicy` of `config`, returning the final response.
+pub fn follow<R, F>(config: &ClientConfig, mut request: RequestMem, mut send: F) -> Result<R, Error>
+where
+    R: ResponseHead,
+    F: FnMut(&RequestMem) -> Result<R, Error>,
+{
+    let mut previous = Vec::new();
+    loop {
+        let response = send(&request)?;
+        let next = match location(&request.header.url, &response) {
+            Some(next) => next,
+            None => return Ok(response),
+        };
+
+        previous.push(request.header.url.clone());
+        let attempt = RedirectAttempt::new(response.status(), &next, &previous);
+        match config.redirect.redirect(&attempt) {
+            RedirectAction::Follow => {}
+            RedirectAction::Stop => return Ok(response),
+            RedirectAction::Error(msg) => return Err(ErrorKind::Redirect(msg).into()),
+        }
+
+        if next.scheme() != "http" && next.scheme() != "https" {
+            return Err(ErrorKind::Redirect(format!("unsupported scheme: {}", next)).into());
+        }
+        debug!("Redirecting {} to {}", request.header.url, next);
+        redirect_request(config, &mut request, response.status(), next);
+    }
+}
+
+/// The URL a response redirects to, if it is a redirect.
+fn location<R: ResponseHead>(url: &Url, response: &R) -> Option<Url> {
+    match response.status() {
+        StatusCode::MOVED_PERMANENTLY
+        | StatusCode::FOUND
+        | StatusCode::SEE_OTHER
+        | StatusCode::TEMPORARY_REDIRECT
+        | StatusCode::PERMANENT_REDIRECT => {}
+        _ => return None,
+    }
+
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    url.join(location).ok()
+}
+
+/// Turn `request` into the request made when following a redirect to `next`.
+fn redirect_request(
+    config: &ClientConfig,
+    request: &mut RequestMem,
+    status: StatusCode,
+    next: Url,
+) {
+    let header = &mut request.header;
+
+    // 301, 302 and 303 continue with a GET request without body, 307 and 308 repeat the
+    // request as is.
+    match status {
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER => {
+            for name in &[
+                TRANSFER_ENCODING,
+                CONTENT_ENCODING,
+                CONTENT_TYPE,
+                CONTENT_LENGTH,
+            ] {
+                header.headers.remove(name);
+            }
+            request.body = None;
+            if header.method != Method::GET && header.method != Method::HEAD {
+                header.method = Method::GET;
+            }
+        }
+        _ => {}
+    }
+
+    if config.referer {
+        if let Some(referer) = make_referer(&next, &header.url) {
+            header.headers.insert(REFERER, referer);
+        }
+    }
+    remove_sensitive_headers(&mut header.headers, &next, &header.url);
+    header.url = next;
+}
+
+fn make_referer(next: &Url, previous: &Url) -> Option<HeaderValue> {
+    // Don't leak the URL of a secure page to an insecure one.
+    if next.scheme() == "http" && previous.scheme() == "https" {
+        return None;
+    }
+
+    let mut referer = previous.clone();
+    let _ = referer.set_username("");
+    let _ = referer.set_password(None);
+    referer.set_fragment(None);
+    referer.as_str().parse().ok()
+}
+
+fn remove_sensitive_headers(headers: &mut HeaderMap, next: &Url, previous: &Url) {
+    let cross_host = next.host_str() != previous.host_str()
+        || next.port_or_known_default() != previous.port_or_known_default();
+    if cross_host {
+        headers.remove(AUTHORIZATION);
+        headers.remove(COOKIE);
+        headers.remove("cookie2");
+        headers.remove(PROXY_AUTHORIZATION);
+        headers.remove(WWW_AUTHENTICATE);
+    }
+}
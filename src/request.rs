@@ -1,60 +1,230 @@
-use body::Body;
+//! The `Request` passed to `Client::execute`, and a `Builder` constructing one without a client.
+//!
+//! # Examples
+//!
+//! A client answering every request with its own URL:
+//!
+//! ```
+//! use reqwest_mock::config::ClientConfig;
+//! use reqwest_mock::request::Request;
+//! use reqwest_mock::{Client, Error, Method, Response, StatusCode};
+//!
+//! struct EchoClient {
+//!     config: ClientConfig,
+//! }
+//!
+//! impl Client for EchoClient {
+//!     fn execute(&self, _: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+//!         Ok(Response {
+//!             url: request.url().clone(),
+//!             status: StatusCode::OK,
+//!             headers: Default::default(),
+//!             body: request.url().as_str().into(),
+//!         })
+//!     }
+//!
+//!     fn config(&self) -> &ClientConfig {
+//!         &self.config
+//!     }
+//!
+//!     fn config_mut(&mut self) -> &mut ClientConfig {
+//!         &mut self.config
+//!     }
+//! }
+//!
+//! let client = EchoClient { config: ClientConfig::default() };
+//! let request = Request::builder(Method::GET, "https://example.com/echo").build().unwrap();
+//! let response = client.execute(None, request).unwrap();
+//! assert_eq!(response.body_to_utf8().unwrap(), "https://example.com/echo");
+//! ```
+
+use body::{Body, BodyMem, LargeBody};
+use error::Error;
 use http::Request as HttpRequest;
-use reqwest::header::HeaderMap;
-use reqwest::{Method, Url};
+use http::Uri;
+use reqwest::header::{HeaderMap, HeaderValue, IntoHeaderName};
+use reqwest::{IntoUrl, Method, Url};
 use serde::de::Error as DeError;
 use serde::de::{Deserialize, Deserializer, MapAccess, Unexpected, Visitor};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::convert::TryFrom;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct RequestHeader {
+pub(crate) struct RequestHeader {
     pub url: Url,
     pub method: Method,
     pub headers: HeaderMap,
 }
 
+/// A HTTP request to be executed by a `Client`.
 #[derive(Debug)]
 pub struct Request {
-    pub header: RequestHeader,
-    pub body: Option<Body>,
+    pub(crate) header: RequestHeader,
+    pub(crate) body: Option<Body>,
 }
 
 impl Request {
-    pub(crate) fn to_mem(self) -> Result<RequestMem, ::std::io::Error> {
+    /// Create a request without headers and body.
+    pub fn new(method: Method, url: Url) -> Self {
+        Request {
+            header: RequestHeader {
+                url,
+                method,
+                headers: HeaderMap::new(),
+            },
+            body: None,
+        }
+    }
+
+    /// Return a `Builder` for a request with `method` to `url`.
+    pub fn builder<U: IntoUrl>(method: Method, url: U) -> Builder {
+        let text = url.as_str().to_string();
+        Builder {
+            request: url
+                .into_url()
+                .map_err(|e| Error::invalid_url(&text, e))
+                .map(|url| Request::new(method, url)),
+        }
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        &self.header.method
+    }
+
+    /// A mutable reference to the method of the request.
+    pub fn method_mut(&mut self) -> &mut Method {
+        &mut self.header.method
+    }
+
+    /// The URL of the request.
+    pub fn url(&self) -> &Url {
+        &self.header.url
+    }
+
+    /// A mutable reference to the URL of the request.
+    pub fn url_mut(&mut self) -> &mut Url {
+        &mut self.header.url
+    }
+
+    /// The headers of the request.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.header.headers
+    }
+
+    /// A mutable reference to the headers of the request.
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.header.headers
+    }
+
+    /// The body of the request, if it has one.
+    pub fn body(&self) -> Option<&Body> {
+        self.body.as_ref()
+    }
+
+    /// A mutable reference to the body of the request.
+    pub fn body_mut(&mut self) -> &mut Option<Body> {
+        &mut self.body
+    }
+
+    /// Read the body, see `Body::into_mem` for the meaning of `body_limit`.
+    pub(crate) fn into_mem(self, body_limit: Option<u64>) -> Result<RequestMem, ::std::io::Error> {
         Ok(RequestMem {
             header: self.header,
             body: match self.body {
-                Some(b) => Some(b.try_to_vec()?),
+                Some(b) => Some(b.into_mem(body_limit)?),
                 None => None,
             },
         })
     }
 }
 
-impl<T> From<HttpRequest<T>> for Request where T: Into<Body> {
-    fn from(r: HttpRequest<T>) -> Self {
+/// Fails if the URI is not an absolute URL, e. g. only a path.
+impl<T> TryFrom<HttpRequest<T>> for Request
+where
+    T: Into<Body>,
+{
+    type Error = Error;
+
+    fn try_from(r: HttpRequest<T>) -> Result<Self, Error> {
+        let (parts, body) = r.into_parts();
+        let uri = parts.uri.to_string();
+        let url = Url::parse(&uri).map_err(|e| Error::invalid_url(&uri, e))?;
         let header = RequestHeader {
-	    // TODO: Handle error when converting.
-	    //  Potentially https://github.com/seanmonstar/reqwest/issues/668 would provide a solution in the future.
-            url: Url::parse(&format!("{}", r.uri())).unwrap(),
-            method: r.method().clone(),
-            headers: r.headers().clone(),
+            url,
+            method: parts.method,
+            headers: parts.headers,
         };
 
-        Request {
+        Ok(Request {
             header,
-            body: Some(r.into_body().into()),
+            body: Some(body.into()),
+        })
+    }
+}
+
+/// Fails if the URL is not a valid URI. A missing body is converted into an empty one.
+impl TryFrom<Request> for HttpRequest<Body> {
+    type Error = Error;
+
+    fn try_from(r: Request) -> Result<Self, Error> {
+        let uri = Uri::try_from(r.header.url.as_str())
+            .map_err(|e| Error::invalid_url(r.header.url.as_str(), e))?;
+        let mut http_req = HttpRequest::new(r.body.unwrap_or_else(|| Vec::new().into()));
+        *http_req.method_mut() = r.header.method;
+        *http_req.uri_mut() = uri;
+        *http_req.headers_mut() = r.header.headers;
+        Ok(http_req)
+    }
+}
+
+/// Builds a `Request` without a client.
+///
+/// Errors, like an invalid URL, are returned by `build`.
+#[derive(Debug)]
+#[must_use]
+pub struct Builder {
+    request: Result<Request, Error>,
+}
+
+impl Builder {
+    /// Add a header to the request.
+    pub fn header<H: IntoHeaderName>(mut self, name: H, value: HeaderValue) -> Self {
+        if let Ok(ref mut request) = self.request {
+            request.header.headers.insert(name, value);
+        }
+        self
+    }
+
+    /// Add multiple headers to the request.
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        if let Ok(ref mut request) = self.request {
+            request.header.headers.extend(headers);
+        }
+        self
+    }
+
+    /// Set the body of the request.
+    pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
+        if let Ok(ref mut request) = self.request {
+            request.body = Some(body.into());
         }
+        self
+    }
+
+    /// Return the resulting `Request`.
+    pub fn build(self) -> Result<Request, Error> {
+        self.request
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct RequestMem {
+pub(crate) struct RequestMem {
     pub header: RequestHeader,
-    pub body: Option<Vec<u8>>,
+    pub body: Option<BodyMem>,
 }
 
 impl From<RequestMem> for Request {
@@ -66,13 +236,20 @@ impl From<RequestMem> for Request {
     }
 }
 
-/// We need this so we can generate unique filenames for each request.
+/// The names of replay files were once generated from this hash, it is still used to find
+/// them. It must not change.
 impl Hash for RequestMem {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.header.url.hash(state);
         self.header.method.hash(state);
         ::helper::serialize_headers(&self.header.headers).hash(state);
-        self.body.hash(state);
+        // Bodies in memory are hashed like before large bodies were introduced, so the
+        // names of existing replay files don't change.
+        match self.body {
+            Some(BodyMem::Bytes(ref bytes)) => Some(bytes).hash(state),
+            Some(BodyMem::Large(ref large)) => large.hash(state),
+            None => None::<Vec<u8>>.hash(state),
+        }
     }
 }
 
@@ -81,11 +258,18 @@ impl Serialize for RequestMem {
     where
         S: Serializer,
     {
-        let mut req = serializer.serialize_struct("Request", 4)?;
+        let mut req = serializer.serialize_struct("Request", 5)?;
 
         req.serialize_field("url", self.header.url.as_ref())?;
         req.serialize_field("method", self.header.method.as_ref())?;
-        req.serialize_field("body", &self.body)?;
+        match self.body {
+            Some(BodyMem::Bytes(ref bytes)) => {
+                let (field, value) = ::helper::store_body(bytes);
+                req.serialize_field(field, &value)?;
+            }
+            Some(BodyMem::Large(ref large)) => req.serialize_field("body_digest", large)?,
+            None => {}
+        }
         req.serialize_field(
             "headers",
             &::helper::serialize_headers(&self.header.headers),
@@ -106,6 +290,12 @@ impl<'de> Deserialize<'de> for RequestMem {
             Url,
             Method,
             Body,
+            #[serde(rename = "body_json")]
+            BodyJson,
+            #[serde(rename = "body_base64")]
+            BodyBase64,
+            #[serde(rename = "body_digest")]
+            BodyDigest,
             Headers,
         }
 
@@ -124,7 +314,8 @@ impl<'de> Deserialize<'de> for RequestMem {
             {
                 let mut url = None;
                 let mut method = None;
-                let mut body = None;
+                let mut body: Option<Vec<u8>> = None;
+                let mut body_digest: Option<LargeBody> = None;
                 let mut headers = None;
 
                 while let Some(key) = map.next_key()? {
@@ -147,11 +338,25 @@ impl<'de> Deserialize<'de> for RequestMem {
                                 DeError::invalid_value(Unexpected::Str(s.as_ref()), &"method")
                             })?);
                         }
-                        Field::Body => {
+                        Field::Body | Field::BodyJson | Field::BodyBase64 => {
+                            let field = match key {
+                                Field::Body => ::helper::F_BODY,
+                                Field::BodyJson => ::helper::F_BODY_JSON,
+                                _ => ::helper::F_BODY_BASE64,
+                            };
                             if body.is_some() {
-                                return Err(DeError::duplicate_field("body"));
+                                return Err(DeError::duplicate_field(field));
                             }
-                            body = map.next_value()?;
+                            body = Some(
+                                ::helper::load_body(field, map.next_value()?)
+                                    .map_err(DeError::custom)?,
+                            );
+                        }
+                        Field::BodyDigest => {
+                            if body_digest.is_some() {
+                                return Err(DeError::duplicate_field("body_digest"));
+                            }
+                            body_digest = map.next_value()?;
                         }
                         Field::Headers => {
                             if headers.is_some() {
@@ -168,12 +373,23 @@ impl<'de> Deserialize<'de> for RequestMem {
                         method: method.ok_or_else(|| DeError::missing_field("method"))?,
                         headers: headers.ok_or_else(|| DeError::missing_field("headers"))?,
                     },
-                    body: body,
+                    body: match body_digest {
+                        Some(large) => Some(BodyMem::Large(large)),
+                        None => body.map(BodyMem::Bytes),
+                    },
                 })
             }
         }
 
-        const FIELDS: &'static [&'static str] = &["url", "method", "body"];
+        const FIELDS: &[&str] = &[
+            "url",
+            "method",
+            "body",
+            "body_json",
+            "body_base64",
+            "body_digest",
+            "headers",
+        ];
         deserializer.deserialize_struct("Request", FIELDS, RequestVisitor {})
     }
 }
@@ -194,12 +410,71 @@ mod tests {
             header: RequestHeader {
                 url: Url::parse("https://example.com").unwrap(),
                 method: Method::GET,
-                headers: headers,
+                headers,
+            },
+            body: Some(BodyMem::Bytes(vec![2, 4, 11, 32, 99, 1, 4, 5])),
+        };
+
+        let json = ::serde_json::to_string(&req1).unwrap();
+        assert!(json.contains(r#""body_base64":"AgQLIGMBBAU=""#));
+        let req2 = ::serde_json::from_str(json.as_ref()).unwrap();
+        assert_eq!(req1, req2);
+    }
+
+    #[test]
+    fn builder() {
+        use reqwest::header::CONTENT_TYPE;
+
+        let req = Request::builder(Method::PUT, "https://example.com/items/1")
+            .header(CONTENT_TYPE, HeaderValue::from_static("text/plain"))
+            .body("item")
+            .build()
+            .unwrap();
+        assert_eq!(req.method(), Method::PUT);
+        assert_eq!(req.url().as_str(), "https://example.com/items/1");
+        assert_eq!(req.headers()[CONTENT_TYPE], "text/plain");
+        assert_eq!(req.body().and_then(Body::as_bytes), Some(&b"item"[..]));
+
+        assert!(Request::builder(Method::GET, "not a url").build().is_err());
+    }
+
+    #[test]
+    fn http_request() {
+        let http_req = HttpRequest::post("https://example.com/path?q=1")
+            .header("x-test", "yes")
+            .body("body")
+            .unwrap();
+        let req = Request::try_from(http_req).unwrap();
+        assert_eq!(req.header.url.as_str(), "https://example.com/path?q=1");
+        assert_eq!(req.header.method, Method::POST);
+        assert_eq!(req.header.headers["x-test"], "yes");
+
+        let http_req = HttpRequest::<Body>::try_from(req).unwrap();
+        assert_eq!(http_req.uri(), "https://example.com/path?q=1");
+        assert_eq!(http_req.method(), Method::POST);
+        assert_eq!(http_req.headers()["x-test"], "yes");
+        assert_eq!(http_req.into_body().try_to_vec().unwrap(), b"body");
+
+        // Only absolute URIs can be converted.
+        let http_req = HttpRequest::get("/path").body("").unwrap();
+        assert!(Request::try_from(http_req).is_err());
+    }
+
+    #[test]
+    fn serde_large_body() {
+        let body = Body::from(vec![7; 100]).into_mem(Some(10)).unwrap();
+        let req1 = RequestMem {
+            header: RequestHeader {
+                url: Url::parse("https://example.com").unwrap(),
+                method: Method::POST,
+                headers: HeaderMap::new(),
             },
-            body: Some(vec![2, 4, 11, 32, 99, 1, 4, 5]),
+            body: Some(body),
         };
 
         let json = ::serde_json::to_string(&req1).unwrap();
+        assert!(!json.contains(r#""body":"#));
+        assert!(json.contains(r#""body_digest":{"sha256":""#));
         let req2 = ::serde_json::from_str(json.as_ref()).unwrap();
         assert_eq!(req1, req2);
     }
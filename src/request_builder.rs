@@ -1,52 +1,280 @@
 use body::Body;
 use client::Client;
-use error::{Error, ResultExt};
+use config::ClientConfig;
+use error::{Error, ErrorKind};
+use multipart::Form;
 use request::{Request, RequestHeader};
-use reqwest::header::{HeaderMap, HeaderValue, IntoHeaderName};
+use reqwest::header::{HeaderMap, HeaderValue, IntoHeaderName, AUTHORIZATION, CONTENT_TYPE};
 use reqwest::{IntoUrl, Method, Url};
-use response::Response;
+use response::{Response, StreamingResponse};
+use serde::Serialize;
+use std::fmt::Display;
+use std::time::Duration;
 
 pub struct RequestBuilder<'cl, Cl: Client + 'cl> {
     client: &'cl Cl,
-
-    url: Result<Url, Error>,
-    method: Method,
-    headers: HeaderMap,
-    body: Option<Body>,
+    request: PendingRequest,
 }
 
 impl<'cl, Cl: Client + 'cl> RequestBuilder<'cl, Cl> {
     #[doc(hidden)]
     pub fn new<U: IntoUrl>(client: &'cl Cl, url: U, method: Method) -> Self {
         RequestBuilder {
-            client: client,
-            url: url.into_url().chain_err(|| "invalid url"),
-            method: method,
-            headers: HeaderMap::new(),
-            body: None,
+            client,
+            request: PendingRequest::new(client.config(), url, method),
         }
     }
 
     /// Add a header to the request.
     pub fn header<H: IntoHeaderName>(mut self, name: H, value: HeaderValue) -> Self {
-        self.headers.insert(name, value);
+        self.request.headers.insert(name, value);
         self
     }
 
     /// Add multiple headers to the request.
     pub fn headers(mut self, headers: HeaderMap) -> Self {
-        self.headers.extend(headers);
+        self.request.headers.extend(headers);
+        self
+    }
+
+    /// Enable HTTP basic authentication.
+    pub fn basic_auth<U: Display, P: Display>(mut self, username: U, password: Option<P>) -> Self {
+        self.request.basic_auth(username, password);
+        self
+    }
+
+    /// Enable HTTP bearer authentication.
+    pub fn bearer_auth<T: Display>(mut self, token: T) -> Self {
+        self.request.bearer_auth(token);
+        self
+    }
+
+    /// Append the serialized `query` to the query string of the URL.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use reqwest_mock::{Client, DirectClient};
+    /// let client = DirectClient::new();
+    /// let request = client
+    ///     .get("https://example.com/search")
+    ///     .query(&[("q", "reqwest mock"), ("page", "2")]);
+    /// ```
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.request.query(query);
         self
     }
 
     /// Set the body of the request.
     pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
-        self.body = Some(body.into());
+        self.request.body = Some(body.into());
+        self
+    }
+
+    /// Set the body of the request to `json` serialized as JSON.
+    ///
+    /// Also sets the `Content-Type` header to `application/json`, unless it was set already.
+    pub fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.request.json(json);
+        self
+    }
+
+    /// Set the body of the request to `form` serialized as URL encoded form.
+    ///
+    /// Also sets the `Content-Type` header to `application/x-www-form-urlencoded`, unless it
+    /// was set already.
+    pub fn form<T: Serialize + ?Sized>(mut self, form: &T) -> Self {
+        self.request.form(form);
+        self
+    }
+
+    /// Set the body of the request to a `multipart/form-data` form.
+    ///
+    /// Also sets the `Content-Type` header including the boundary of the form.
+    pub fn multipart(mut self, form: Form) -> Self {
+        self.request.multipart(form);
+        self
+    }
+
+    /// Set a timeout for this request only, overriding the one of the client's config.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.request.timeout = Some(timeout);
         self
     }
 
     /// Send the request.
     pub fn send(self) -> Result<Response, Error> {
+        let client = self.client;
+        let (request, config) = self.request.build(client.config())?;
+        client.execute(config.as_ref(), request)
+    }
+
+    /// Send the request, returning the response before its body was read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use reqwest_mock::{Client, DirectClient};
+    /// use std::fs::File;
+    /// use std::io;
+    ///
+    /// let client = DirectClient::new();
+    /// let mut response = client
+    ///     .get("https://example.com/large.tar.gz")
+    ///     .send_streaming()
+    ///     .unwrap();
+    /// let mut file = File::create("large.tar.gz").unwrap();
+    /// io::copy(&mut response, &mut file).unwrap();
+    /// ```
+    pub fn send_streaming(self) -> Result<StreamingResponse, Error> {
+        let client = self.client;
+        let (request, config) = self.request.build(client.config())?;
+        client.execute_streaming(config.as_ref(), request)
+    }
+}
+
+/// The state of a request being built, shared by `RequestBuilder` and
+/// `blocking::RequestBuilder`.
+pub(crate) struct PendingRequest {
+    pub url: Result<Url, Error>,
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Option<Body>,
+    pub timeout: Option<Duration>,
+
+    /// An error which occurred while building the request, returned by `send`.
+    error: Option<Error>,
+}
+
+impl PendingRequest {
+    pub fn new<U: IntoUrl>(config: &ClientConfig, url: U, method: Method) -> Self {
+        let url = match config.base_url {
+            Some(ref base) => base
+                .join(url.as_str())
+                .map_err(|e| Error::invalid_url(url.as_str(), e)),
+            None => {
+                let text = url.as_str().to_string();
+                url.into_url().map_err(|e| Error::invalid_url(&text, e))
+            }
+        };
+
+        PendingRequest {
+            url,
+            method,
+            headers: config.default_request_headers(),
+            body: None,
+            timeout: None,
+            error: None,
+        }
+    }
+
+    pub fn basic_auth<U: Display, P: Display>(&mut self, username: U, password: Option<P>) {
+        let credentials = match password {
+            Some(password) => format!("{}:{}", username, password),
+            None => format!("{}:", username),
+        };
+        self.authorization(format!("Basic {}", ::base64::encode(&credentials)))
+    }
+
+    pub fn bearer_auth<T: Display>(&mut self, token: T) {
+        self.authorization(format!("Bearer {}", token))
+    }
+
+    fn authorization(&mut self, value: String) {
+        match HeaderValue::from_str(&value) {
+            Ok(mut value) => {
+                value.set_sensitive(true);
+                self.headers.insert(AUTHORIZATION, value);
+            }
+            Err(e) => self.set_error(Error::with_source(ErrorKind::InvalidRequest, e)),
+        }
+    }
+
+    pub fn query<T: Serialize + ?Sized>(&mut self, query: &T) {
+        let result = match self.url {
+            Ok(ref mut url) => {
+                let result = {
+                    let mut pairs = url.query_pairs_mut();
+                    query
+                        .serialize(::serde_urlencoded::Serializer::new(&mut pairs))
+                        .map(|_| ())
+                };
+                if url.query() == Some("") {
+                    url.set_query(None);
+                }
+                result
+            }
+            Err(_) => Ok(()),
+        };
+        if let Err(e) = result {
+            self.set_error(Error::with_source(ErrorKind::InvalidRequest, e));
+        }
+    }
+
+    pub fn json<T: Serialize + ?Sized>(&mut self, json: &T) {
+        match ::serde_json::to_vec(json) {
+            Ok(body) => {
+                self.set_content_type("application/json");
+                self.body = Some(body.into());
+            }
+            Err(e) => self.set_error(Error::with_source(ErrorKind::InvalidRequest, e)),
+        }
+    }
+
+    pub fn form<T: Serialize + ?Sized>(&mut self, form: &T) {
+        match ::serde_urlencoded::to_string(form) {
+            Ok(body) => {
+                self.set_content_type("application/x-www-form-urlencoded");
+                self.body = Some(body.into());
+            }
+            Err(e) => self.set_error(Error::with_source(ErrorKind::InvalidRequest, e)),
+        }
+    }
+
+    pub fn multipart(&mut self, form: Form) {
+        self.headers.insert(CONTENT_TYPE, form.content_type());
+        self.body = Some(form.into());
+    }
+
+    fn set_content_type(&mut self, value: &'static str) {
+        if !self.headers.contains_key(CONTENT_TYPE) {
+            self.headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static(value));
+        }
+    }
+
+    /// Remember the first error that occurred.
+    pub fn set_error(&mut self, error: Error) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Copy the request, unless its body can't be copied or building it failed already.
+    pub fn try_clone(&self) -> Option<PendingRequest> {
+        if self.error.is_some() {
+            return None;
+        }
+        let body = match self.body {
+            Some(ref body) => Some(body.try_clone()?),
+            None => None,
+        };
+        Some(PendingRequest {
+            url: Ok(self.url.as_ref().ok()?.clone()),
+            method: self.method.clone(),
+            headers: self.headers.clone(),
+            body,
+            timeout: self.timeout,
+            error: None,
+        })
+    }
+
+    /// Return the request and the config to use for it, if it differs from `config`.
+    pub fn build(self, config: &ClientConfig) -> Result<(Request, Option<ClientConfig>), Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
         let request = Request {
             header: RequestHeader {
                 url: self.url?,
@@ -55,7 +283,194 @@ impl<'cl, Cl: Client + 'cl> RequestBuilder<'cl, Cl> {
             },
             body: self.body,
         };
+        let config = self.timeout.map(|timeout| {
+            let mut config = config.clone();
+            config.timeout = Some(timeout);
+            config
+        });
+        Ok((request, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+
+    /// A client remembering the last request and config instead of performing anything.
+    struct CaptureClient {
+        config: ClientConfig,
+        request: RefCell<Option<(Request, Option<Duration>)>>,
+    }
+
+    impl CaptureClient {
+        fn new() -> Self {
+            CaptureClient {
+                config: ClientConfig::default(),
+                request: RefCell::new(None),
+            }
+        }
+
+        fn captured(&self) -> (RequestHeader, Option<String>, Option<Duration>) {
+            let (request, timeout) = self.request.borrow_mut().take().unwrap();
+            let body = request
+                .body
+                .map(|b| String::from_utf8(b.try_to_vec().unwrap()).unwrap());
+            (request.header, body, timeout)
+        }
+    }
+
+    impl Client for CaptureClient {
+        fn execute(
+            &self,
+            config: Option<&ClientConfig>,
+            request: Request,
+        ) -> Result<Response, Error> {
+            let timeout = config.unwrap_or(&self.config).timeout;
+            let url = request.header.url.clone();
+            *self.request.borrow_mut() = Some((request, timeout));
+            Ok(Response {
+                url,
+                status: StatusCode::OK,
+                headers: HeaderMap::new(),
+                body: Vec::new(),
+            })
+        }
+
+        fn config(&self) -> &ClientConfig {
+            &self.config
+        }
+
+        fn config_mut(&mut self) -> &mut ClientConfig {
+            &mut self.config
+        }
+    }
+
+    #[test]
+    fn query() {
+        let client = CaptureClient::new();
+        let mut query = BTreeMap::new();
+        query.insert("a", "1 2");
+        query.insert("b", "&");
+        client
+            .get("http://example.com/?x=y")
+            .query(&query)
+            .send()
+            .unwrap();
+        let (header, _, _) = client.captured();
+        assert_eq!(header.url.as_str(), "http://example.com/?x=y&a=1+2&b=%26");
+
+        client
+            .get("http://example.com/")
+            .query(&Vec::<(String, String)>::new())
+            .send()
+            .unwrap();
+        let (header, _, _) = client.captured();
+        assert_eq!(header.url.as_str(), "http://example.com/");
+    }
+
+    #[test]
+    fn json() {
+        let client = CaptureClient::new();
+        let mut json = BTreeMap::new();
+        json.insert("answer", 42);
+        client
+            .post("http://example.com/")
+            .json(&json)
+            .send()
+            .unwrap();
+        let (header, body, _) = client.captured();
+        assert_eq!(header.headers[CONTENT_TYPE], "application/json");
+        assert_eq!(body.unwrap(), r#"{"answer":42}"#);
+
+        // An explicit content type is kept.
+        client
+            .post("http://example.com/")
+            .header(CONTENT_TYPE, "application/vnd.api+json".parse().unwrap())
+            .json(&json)
+            .send()
+            .unwrap();
+        let (header, _, _) = client.captured();
+        assert_eq!(header.headers[CONTENT_TYPE], "application/vnd.api+json");
+    }
+
+    #[test]
+    fn form() {
+        let client = CaptureClient::new();
+        client
+            .post("http://example.com/")
+            .form(&[("name", "Jane Doe"), ("lang", "rust")])
+            .send()
+            .unwrap();
+        let (header, body, _) = client.captured();
+        assert_eq!(
+            header.headers[CONTENT_TYPE],
+            "application/x-www-form-urlencoded"
+        );
+        assert_eq!(body.unwrap(), "name=Jane+Doe&lang=rust");
+
+        // Only sequences of pairs and maps can be form encoded.
+        assert!(client.post("http://example.com/").form(&42).send().is_err());
+    }
+
+    #[test]
+    fn auth() {
+        let client = CaptureClient::new();
+        client
+            .get("http://example.com/")
+            .basic_auth("Aladdin", Some("open sesame"))
+            .send()
+            .unwrap();
+        let (header, _, _) = client.captured();
+        assert_eq!(
+            header.headers[AUTHORIZATION],
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert!(header.headers[AUTHORIZATION].is_sensitive());
+
+        client
+            .get("http://example.com/")
+            .bearer_auth("token")
+            .send()
+            .unwrap();
+        let (header, _, _) = client.captured();
+        assert_eq!(header.headers[AUTHORIZATION], "Bearer token");
+    }
+
+    #[test]
+    fn multipart() {
+        let client = CaptureClient::new();
+        client
+            .post("http://example.com/")
+            .multipart(Form::new().text("a", "b"))
+            .send()
+            .unwrap();
+        let (header, body, _) = client.captured();
+        assert_eq!(
+            header.headers[CONTENT_TYPE],
+            format!(
+                "multipart/form-data; boundary={}",
+                ::multipart::DEFAULT_BOUNDARY
+            )
+        );
+        assert!(body.unwrap().contains("name=\"a\"\r\n\r\nb\r\n"));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut client = CaptureClient::new();
+        client.config_mut().timeout = Some(Duration::from_secs(30));
+
+        client.get("http://example.com/").send().unwrap();
+        assert_eq!(client.captured().2, Some(Duration::from_secs(30)));
 
-        self.client.execute(None, request)
+        client
+            .get("http://example.com/")
+            .timeout(Duration::from_secs(1))
+            .send()
+            .unwrap();
+        assert_eq!(client.captured().2, Some(Duration::from_secs(1)));
     }
 }
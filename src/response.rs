@@ -1,12 +1,16 @@
-use base64;
-use error::Error;
+use encoding_rs::{Encoding, UTF_8};
+use error::{Error, ErrorKind};
+use helper::{F_BODY, F_BODY_BASE64, F_BODY_JSON};
 use http::Response as HttpResponse;
-use reqwest::header::HeaderMap;
+use mime::Mime;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE};
 use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
 use serde::de::Error as DeError;
 use serde::de::{Deserialize, Deserializer, MapAccess, Unexpected, Visitor};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::fmt;
+use std::io::{Cursor, Read};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Response {
@@ -27,13 +31,153 @@ impl Response {
     pub fn body_to_utf8(&self) -> Result<String, Error> {
         Ok(String::from_utf8(self.body.clone())?)
     }
+
+    /// The body of the response.
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Decode the body as text, using the charset of the `Content-Type` header.
+    ///
+    /// UTF-8 is used if the header doesn't specify a charset. Invalid sequences are replaced
+    /// with `U+FFFD`.
+    pub fn text(&self) -> String {
+        self.text_with_charset("utf-8")
+    }
+
+    /// Decode the body as text, using the charset of the `Content-Type` header or
+    /// `default_encoding` if the header doesn't specify one.
+    pub fn text_with_charset(&self, default_encoding: &str) -> String {
+        let content_type = self.content_type();
+        let label = content_type
+            .as_ref()
+            .and_then(|mime| mime.get_param("charset"))
+            .map(|charset| charset.as_str())
+            .unwrap_or(default_encoding);
+        let encoding = Encoding::for_label(label.as_bytes()).unwrap_or(UTF_8);
+        let (text, _, _) = encoding.decode(&self.body);
+        text.into_owned()
+    }
+
+    /// Deserialize the body as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(::serde_json::from_slice(&self.body)?)
+    }
+
+    /// The parsed `Content-Type` header, if it is present and valid.
+    pub fn content_type(&self) -> Option<Mime> {
+        self.headers.get(CONTENT_TYPE)?.to_str().ok()?.parse().ok()
+    }
+
+    /// The value of the `Content-Length` header, if it is present and valid.
+    ///
+    /// Note that this is not necessarily the length of `body`, for example if the body was
+    /// decompressed.
+    pub fn content_length(&self) -> Option<u64> {
+        content_length(&self.headers)
+    }
+
+    /// Turn a response with a client (4xx) or server (5xx) error status into an error,
+    /// carrying the status and body of the response.
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        if self.status.is_client_error() || self.status.is_server_error() {
+            Err(Error::new(ErrorKind::Status(self.status, self.body)))
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Convert an `http::Response` received from `url`, which it doesn't carry itself.
+    ///
+    /// The `Url` in the extensions of responses converted from a `Response` can be passed.
+    pub fn from_http<T>(response: HttpResponse<T>, url: Url) -> Response
+    where
+        T: Into<Vec<u8>>,
+    {
+        let (parts, body) = response.into_parts();
+        Response {
+            url,
+            status: parts.status,
+            headers: parts.headers,
+            body: body.into(),
+        }
+    }
+}
+
+/// The value of the `Content-Length` header in `headers`, if it is present and valid.
+pub(crate) fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// A response whose body is read as it is received, instead of being buffered in memory.
+///
+/// Reading from it reads the body. Use `into_response` to read the whole body into a
+/// `Response`.
+pub struct StreamingResponse {
+    /// The final url of this response.
+    pub url: Url,
+
+    /// Status code.
+    pub status: StatusCode,
+
+    /// Headers
+    pub headers: HeaderMap,
+
+    body: Box<dyn Read + Send>,
+}
+
+impl StreamingResponse {
+    pub(crate) fn new<R>(url: Url, status: StatusCode, headers: HeaderMap, body: R) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        StreamingResponse {
+            url,
+            status,
+            headers,
+            body: Box::new(body),
+        }
+    }
+
+    /// Read the rest of the body, returning a buffered `Response`.
+    pub fn into_response(mut self) -> Result<Response, Error> {
+        let mut body = Vec::new();
+        self.body.read_to_end(&mut body)?;
+        Ok(Response {
+            url: self.url,
+            status: self.status,
+            headers: self.headers,
+            body,
+        })
+    }
+}
+
+impl Read for StreamingResponse {
+    fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl fmt::Debug for StreamingResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamingResponse")
+            .field("url", &self.url)
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
 }
 
-const N_RESPONSE: &'static str = "Response";
-const F_URL: &'static str = "url";
-const F_STATUS: &'static str = "status";
-const F_HEADERS: &'static str = "headers";
-const F_BODY: &'static str = "body";
+impl From<Response> for StreamingResponse {
+    fn from(r: Response) -> StreamingResponse {
+        StreamingResponse::new(r.url, r.status, r.headers, Cursor::new(r.body))
+    }
+}
+
+const N_RESPONSE: &str = "Response";
+const F_URL: &str = "url";
+const F_STATUS: &str = "status";
+const F_HEADERS: &str = "headers";
 
 impl Serialize for Response {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -44,9 +188,10 @@ impl Serialize for Response {
 
         res.serialize_field(F_URL, self.url.as_ref())?;
         // TODO: actually the docs for this are hidden
-        res.serialize_field(F_STATUS, &u16::from(self.status.clone()))?;
+        res.serialize_field(F_STATUS, &u16::from(self.status))?;
         res.serialize_field(F_HEADERS, &::helper::serialize_headers(&self.headers))?;
-        res.serialize_field(F_BODY, &base64::encode(&self.body))?;
+        let (field, value) = ::helper::store_body(&self.body);
+        res.serialize_field(field, &value)?;
 
         res.end()
     }
@@ -59,6 +204,10 @@ enum Field {
     Status,
     Headers,
     Body,
+    #[serde(rename = "body_json")]
+    BodyJson,
+    #[serde(rename = "body_base64")]
+    BodyBase64,
 }
 
 struct ResponseVisitor {}
@@ -105,14 +254,18 @@ impl<'de> Visitor<'de> for ResponseVisitor {
                     }
                     headers = Some(::helper::deserialize_headers(&map.next_value()?));
                 }
-                Field::Body => {
+                Field::Body | Field::BodyJson | Field::BodyBase64 => {
+                    let field = match key {
+                        Field::Body => F_BODY,
+                        Field::BodyJson => F_BODY_JSON,
+                        _ => F_BODY_BASE64,
+                    };
                     if body.is_some() {
-                        return Err(DeError::duplicate_field(F_BODY));
+                        return Err(DeError::duplicate_field(field));
                     }
-                    let s: String = map.next_value()?;
-                    body = Some(base64::decode(&s).map_err(|_| {
-                        DeError::invalid_value(Unexpected::Str(s.as_ref()), &F_BODY)
-                    })?);
+                    body = Some(
+                        ::helper::load_body(field, map.next_value()?).map_err(DeError::custom)?,
+                    );
                 }
             }
         }
@@ -126,13 +279,14 @@ impl<'de> Visitor<'de> for ResponseVisitor {
     }
 }
 
+/// The URL of the response is kept as `Url` in the extensions of the `http::Response`.
 impl From<Response> for HttpResponse<Vec<u8>> {
     fn from(r: Response) -> HttpResponse<Vec<u8>> {
-        let mut http_rsp = HttpResponse::builder().status(r.status);
+        let mut http_rsp = HttpResponse::builder().status(r.status).extension(r.url);
         let headers = http_rsp.headers_mut().unwrap();
         for (key, value) in r.headers {
             if let Some(k) = key {
-		headers.append(&k, value);
+                headers.append(&k, value);
             }
         }
         http_rsp.body(r.body).unwrap()
@@ -144,7 +298,14 @@ impl<'de> Deserialize<'de> for Response {
     where
         D: Deserializer<'de>,
     {
-        const FIELDS: &'static [&'static str] = &[F_URL, F_STATUS, F_HEADERS, F_BODY];
+        const FIELDS: &[&str] = &[
+            F_URL,
+            F_STATUS,
+            F_HEADERS,
+            F_BODY,
+            F_BODY_JSON,
+            F_BODY_BASE64,
+        ];
         deserializer.deserialize_struct(N_RESPONSE, FIELDS, ResponseVisitor {})
     }
 }
@@ -152,7 +313,7 @@ impl<'de> Deserialize<'de> for Response {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use reqwest::header::{CONTENT_LENGTH, USER_AGENT};
+    use reqwest::header::USER_AGENT;
 
     fn dummy_response() -> Response {
 
@@ -163,7 +324,7 @@ mod tests {
         Response {
             url: Url::parse("http://example.com/index.html").unwrap(),
             status: StatusCode::OK,
-            headers: headers,
+            headers,
             body: vec![2, 4, 8, 16, 32, 64, 42],
         }
     }
@@ -177,6 +338,71 @@ mod tests {
         assert_eq!(resp1, resp2);
     }
 
+    #[test]
+    fn text() {
+        let mut resp = dummy_response();
+        resp.body = vec![0x47, 0x72, 0xfc, 0xdf, 0x65];
+        resp.headers.insert(
+            CONTENT_TYPE,
+            "text/plain; charset=ISO-8859-1".parse().unwrap(),
+        );
+        assert_eq!(resp.text(), "Grüße");
+
+        // Without a charset UTF-8 is assumed.
+        resp.headers
+            .insert(CONTENT_TYPE, "text/plain".parse().unwrap());
+        assert_eq!(resp.text(), "Gr\u{fffd}\u{fffd}e");
+        assert_eq!(resp.text_with_charset("latin1"), "Grüße");
+    }
+
+    #[test]
+    fn json() {
+        let mut resp = dummy_response();
+        resp.body = br#"{"answer": 42}"#.to_vec();
+        let json: ::serde_json::Value = resp.json().unwrap();
+        assert_eq!(json["answer"], 42);
+        assert!(dummy_response().json::<::serde_json::Value>().is_err());
+    }
+
+    #[test]
+    fn header_accessors() {
+        let mut resp = dummy_response();
+        assert_eq!(resp.content_length(), Some(2000));
+        assert_eq!(resp.content_type(), None);
+
+        resp.headers
+            .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        assert_eq!(resp.content_type(), Some(::mime::APPLICATION_JSON));
+    }
+
+    #[test]
+    fn error_for_status() {
+        assert!(dummy_response().error_for_status().is_ok());
+
+        let mut resp = dummy_response();
+        resp.status = StatusCode::NOT_FOUND;
+        match resp.error_for_status().unwrap_err().kind() {
+            ErrorKind::Status(status, body) => {
+                assert_eq!(*status, StatusCode::NOT_FOUND);
+                assert_eq!(*body, vec![2, 4, 8, 16, 32, 64, 42]);
+            }
+            kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn streaming_response() {
+        let resp = dummy_response();
+        let mut streaming = StreamingResponse::from(resp.clone());
+        let mut head = [0; 2];
+        streaming.read_exact(&mut head).unwrap();
+        assert_eq!(head, [2, 4]);
+
+        let rest = streaming.into_response().unwrap();
+        assert_eq!(rest.body, vec![8, 16, 32, 64, 42]);
+        assert_eq!(rest.headers, resp.headers);
+    }
+
     #[test]
     fn http_response() {
 	let resp = dummy_response();
@@ -186,5 +412,16 @@ mod tests {
 	assert_eq!(http_resp.headers().get(CONTENT_LENGTH).unwrap().to_str().unwrap(), "2000");
 	assert_eq!(http_resp.headers().get(USER_AGENT).unwrap(), "Testing Code");
 	assert_eq!(http_resp.body(), &vec![2u8, 4, 8, 16, 32, 64, 42]);
+
+        // The URL is kept in the extensions.
+        let url = http_resp.extensions().get::<Url>().unwrap().clone();
+        assert_eq!(Response::from_http(http_resp, url), dummy_response());
+
+        let http_resp = HttpResponse::builder().status(404).body("missing").unwrap();
+        let url = Url::parse("http://example.com/missing").unwrap();
+        let resp = Response::from_http(http_resp, url.clone());
+        assert_eq!(resp.url, url);
+        assert_eq!(resp.status, StatusCode::NOT_FOUND);
+        assert_eq!(resp.body, b"missing");
     }
 }
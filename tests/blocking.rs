@@ -0,0 +1,117 @@
+//! Test the reqwest-like `blocking::Client`.
+
+extern crate futures;
+extern crate hyper;
+extern crate regex;
+extern crate reqwest;
+extern crate reqwest_mock;
+mod helper;
+
+use reqwest_mock::blocking::Client;
+use reqwest_mock::header::CONTENT_TYPE;
+use reqwest_mock::{
+    Method, StatusCode, StubClient, StubDefault, StubSettings, StubStrictness, Url,
+};
+use std::io::Read;
+use std::time::Duration;
+
+/// The same call site is compiled against reqwest and this crate.
+macro_rules! get_body {
+    ($client:expr, $url:expr) => {
+        $client
+            .post($url)
+            .header("x-api-key", "secret")
+            .query(&[("page", "2")])
+            .body("41")
+            .timeout(Duration::from_secs(5))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map(|response| {
+                assert_eq!(response.status(), StatusCode::OK);
+                response.text().unwrap()
+            })
+    };
+}
+
+#[test]
+fn blocking_client() {
+    let server = helper::run_server("127.0.0.1:19261".parse().unwrap());
+    let url = "http://127.0.0.1:19261/abc";
+
+    let expected = get_body!(reqwest::blocking::Client::new(), url).unwrap();
+    let body = get_body!(Client::new(), url).unwrap();
+    assert_eq!(body, expected);
+    assert!(body.starts_with("42\nPOST /abc?page=2\n"));
+    assert!(body.contains("secret"));
+
+    // Built requests are executed by the client.
+    let client = Client::builder()
+        .user_agent("MyClient/1.0")
+        .build()
+        .unwrap();
+    let request = client.get(url).body("1").build().unwrap();
+    let mut response = client.execute(request).unwrap();
+    assert_eq!(response.url().as_str(), url);
+    let mut body = String::new();
+    response.read_to_string(&mut body).unwrap();
+    assert!(body.contains("MyClient/1.0"));
+
+    server.terminate();
+}
+
+fn stub_client() -> Client<StubClient> {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::MethodUrl,
+    });
+    client
+        .stub(Url::parse("http://example.com/answer").unwrap())
+        .method(Method::GET)
+        .response()
+        .header(CONTENT_TYPE, "application/json".parse().unwrap())
+        .body(r#"{"answer":42}"#)
+        .mock()
+        .unwrap();
+    client
+        .stub(Url::parse("http://example.com/missing").unwrap())
+        .method(Method::GET)
+        .response()
+        .status_code(StatusCode::NOT_FOUND)
+        .body("not found")
+        .mock()
+        .unwrap();
+    Client::from(client)
+}
+
+#[test]
+fn blocking_stub_client() {
+    let client = stub_client();
+
+    let builder = client.get("http://example.com/answer");
+    let copy = builder.try_clone().unwrap();
+    let response = builder.send().unwrap();
+    let json: ::std::collections::BTreeMap<String, u32> = response.json().unwrap();
+    assert_eq!(json["answer"], 42);
+    assert_eq!(
+        &copy.send().unwrap().bytes().unwrap()[..],
+        br#"{"answer":42}"#
+    );
+
+    let response = client.get("http://example.com/missing").send().unwrap();
+    assert!(response.error_for_status_ref().is_err());
+    let error = response.error_for_status().unwrap_err();
+    match *error.kind() {
+        reqwest_mock::error::ErrorKind::Status(status, ref body) => {
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            assert_eq!(body, b"not found");
+        }
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    // Invalid headers are reported when sending.
+    assert!(client
+        .get("http://example.com/answer")
+        .header("in valid", "value")
+        .send()
+        .is_err());
+}
@@ -8,7 +8,12 @@ extern crate reqwest_mock;
 mod helper;
 
 use reqwest_mock::client::DirectClient;
-use reqwest_mock::{Client, StatusCode};
+use reqwest_mock::config::ClientConfig;
+use reqwest_mock::{Body, Client, StatusCode, Url};
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn direct_client() {
@@ -42,3 +47,155 @@ fn direct_client() {
 
     server.terminate();
 }
+
+#[test]
+fn direct_client_config_defaults() {
+    let server = helper::run_server("127.0.0.1:19242".parse().unwrap());
+
+    let mut client = DirectClient::new();
+    *client.config_mut() = ClientConfig::builder()
+        .base_url(Url::parse("http://127.0.0.1:19242/api/").unwrap())
+        .default_header("x-api-key", "secret".parse().unwrap())
+        .user_agent("MyClient/1.0".parse().unwrap())
+        .build();
+
+    let resp = client.get("users").body("1").send().unwrap();
+    assert_eq!(resp.status, StatusCode::OK);
+    let body = resp.body_to_utf8().unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines[0], "2");
+    assert_eq!(lines[1], "GET /api/users");
+    assert!(body.contains("MyClient/1.0"));
+    assert!(body.contains("secret"));
+
+    // Absolute paths replace the path of the base URL.
+    let resp = client.get("/users").body("1").send().unwrap();
+    let body = resp.body_to_utf8().unwrap();
+    assert_eq!(body.lines().nth(1).unwrap(), "GET /users");
+
+    server.terminate();
+}
+
+#[test]
+fn direct_client_reader_body() {
+    let server = helper::run_server("127.0.0.1:19243".parse().unwrap());
+    let client = DirectClient::new();
+
+    // With a known length.
+    let resp = client
+        .post("http://127.0.0.1:19243/sized")
+        .body(Body::from_reader(Cursor::new("41"), Some(2)))
+        .send()
+        .unwrap();
+    let body = resp.body_to_utf8().unwrap().to_lowercase();
+    assert_eq!(body.lines().next().unwrap(), "42");
+    assert!(body.contains(r#""content-length": "2""#));
+
+    // Sent chunked otherwise.
+    let resp = client
+        .post("http://127.0.0.1:19243/chunked")
+        .body(Body::from_reader(Cursor::new("41"), None))
+        .send()
+        .unwrap();
+    let body = resp.body_to_utf8().unwrap().to_lowercase();
+    assert_eq!(body.lines().next().unwrap(), "42");
+    assert!(body.contains(r#""transfer-encoding": "chunked""#));
+
+    server.terminate();
+}
+
+#[test]
+fn direct_client_streaming() {
+    let server = helper::run_server("127.0.0.1:19244".parse().unwrap());
+    let client = DirectClient::new();
+
+    let mut resp = client
+        .get("http://127.0.0.1:19244/stream")
+        .body("1")
+        .send_streaming()
+        .unwrap();
+    assert_eq!(resp.status, StatusCode::OK);
+    let mut body = String::new();
+    resp.read_to_string(&mut body).unwrap();
+    assert_eq!(body.lines().nth(1).unwrap(), "GET /stream");
+
+    server.terminate();
+}
+
+#[test]
+fn direct_client_connect_error() {
+    // Nothing is listening on the port of a closed listener.
+    let port = ::std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let error = DirectClient::new()
+        .get(format!("http://127.0.0.1:{}/", port))
+        .send()
+        .unwrap_err();
+    assert!(error.is_connect());
+    assert!(::std::error::Error::source(&error).is_some());
+}
+
+/// Run a server on a random port sending `body`, waiting for `delay` before every byte.
+fn run_trickling_server(body: &'static [u8], delay: Duration) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            // Read the request head, the tests never send a body.
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            while !lines.next().unwrap().unwrap().is_empty() {}
+            thread::spawn(move || {
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                );
+                for byte in body {
+                    thread::sleep(delay);
+                    let _ = stream.write_all(&[*byte]);
+                }
+            });
+        }
+    });
+    url
+}
+
+#[test]
+fn direct_client_timeouts() {
+    let url = run_trickling_server(b"abcdef", Duration::from_millis(150));
+    let client = |config: ClientConfig| {
+        let mut client = DirectClient::new();
+        *client.config_mut() = config;
+        client
+    };
+
+    // Every read is faster than the read timeout.
+    let config = ClientConfig::builder()
+        .read_timeout(Duration::from_millis(500))
+        .build();
+    let resp = client(config).get(&url).send().unwrap();
+    assert_eq!(resp.body_to_utf8().unwrap(), "abcdef");
+
+    let config = ClientConfig::builder()
+        .read_timeout(Duration::from_millis(50))
+        .build();
+    assert!(client(config).get(&url).send().unwrap_err().is_timeout());
+
+    // The whole body is slower than the timeout of the request.
+    let config = ClientConfig::builder()
+        .timeout(Duration::from_millis(400))
+        .build();
+    assert!(client(config).get(&url).send().unwrap_err().is_timeout());
+}
+
+#[test]
+fn direct_client_no_timeout() {
+    // Longer than the default timeout of reqwest's blocking client.
+    let url = run_trickling_server(b"a", Duration::from_secs(31));
+    let resp = DirectClient::new().get(&url).send().unwrap();
+    assert_eq!(resp.body_to_utf8().unwrap(), "a");
+}
@@ -0,0 +1,801 @@
+//! Test the `ReplayClient`.
+
+extern crate futures;
+extern crate hyper;
+extern crate regex;
+extern crate reqwest_mock;
+extern crate tempfile;
+mod helper;
+
+use reqwest_mock::config::RedirectPolicy;
+use reqwest_mock::error::ErrorKind;
+use reqwest_mock::header::{ACCEPT, LOCATION, SET_COOKIE};
+use reqwest_mock::{
+    Body, Client, Exhausted, FileNaming, Method, RecordMode, RecordingTarget, ReplayClient,
+    StatusCode, StubClient, StubDefault, StubSettings, StubStrictness, Url,
+};
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Run a server on a random port redirecting `/start` to `/middle` and `/middle` to `/end`.
+/// It responds with the path, or the number of requests received so far for `/poll`.
+///
+/// Returns the base URL of the server and the counter of requests it received.
+fn run_redirect_server() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let hits = Arc::new(AtomicUsize::new(0));
+
+    let counter = hits.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            let received = counter.fetch_add(1, Ordering::SeqCst) + 1;
+
+            // Read the request head, the tests never send a body.
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            let request_line = lines.next().unwrap().unwrap();
+            for line in lines {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+
+            let path = request_line.split(' ').nth(1).unwrap().to_string();
+            let body = match path.as_ref() {
+                "/poll" => format!("poll {}", received),
+                _ => path.clone(),
+            };
+            let response = match path.as_ref() {
+                "/start" => "HTTP/1.1 302 Found\r\nLocation: /middle\r\nSet-Cookie: session=1\r\n",
+                "/middle" => "HTTP/1.1 301 Moved Permanently\r\nLocation: /end\r\n",
+                _ => "HTTP/1.1 200 OK\r\n",
+            };
+            let _ = write!(
+                stream,
+                "{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response,
+                body.len(),
+                body
+            );
+        }
+    });
+
+    (base, hits)
+}
+
+#[test]
+fn redirect_chain() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/start", base);
+
+    let client = ReplayClient::new(RecordingTarget::file(dir.path().join("redirect.json")));
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.url.path(), "/end");
+    assert_eq!(response.body_to_utf8().unwrap(), "/end");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    // Replaying follows the recorded chain without contacting the server.
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.url.path(), "/end");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    // Every hop was recorded, so a stricter policy can be replayed as well.
+    let mut client = ReplayClient::new(RecordingTarget::file(dir.path().join("redirect.json")));
+    client.config_mut().redirect = RedirectPolicy::None;
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.status, StatusCode::FOUND);
+    assert_eq!(response.headers[LOCATION], "/middle");
+    assert_eq!(response.headers[SET_COOKIE], "session=1");
+
+    client.config_mut().redirect = RedirectPolicy::Limit(2);
+    assert!(client.get(&url).send().is_err());
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn redirect_not_recorded() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/start", base);
+
+    let mut client = ReplayClient::new(RecordingTarget::file(dir.path().join("redirect.json")));
+    client.config_mut().redirect = RedirectPolicy::None;
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.status, StatusCode::FOUND);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // Following the redirect requires recording the rest of the chain.
+    client.config_mut().redirect = RedirectPolicy::default();
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.url.path(), "/end");
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn replay_only() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("replay_only.json");
+    let url = format!("{}/end", base);
+
+    let client =
+        ReplayClient::new(RecordingTarget::file(&file)).record_mode(RecordMode::ReplayOnly);
+    let error = client.get(&url).send().unwrap_err();
+    match *error.kind() {
+        ErrorKind::ReplayMissing(ref path) => assert_eq!(path, &file),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+
+    // Forcing a recording still performs the request.
+    client.force_record_next();
+    assert_eq!(
+        client.get(&url).send().unwrap().body_to_utf8().unwrap(),
+        "/end"
+    );
+    assert_eq!(
+        client.get(&url).send().unwrap().body_to_utf8().unwrap(),
+        "/end"
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // Files of another format version are not recorded again.
+    let content = fs::read_to_string(&file).unwrap();
+    assert!(content.contains("\"body\": \"/end\""));
+    fs::write(
+        &file,
+        content.replace("\"format_version\": 5", "\"format_version\": 2"),
+    )
+    .unwrap();
+    let error = client.get(&url).send().unwrap_err();
+    match *error.kind() {
+        ErrorKind::ReplayFormatMismatch {
+            found, expected, ..
+        } => assert_eq!((found, expected), (Some(2), 5)),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn repeated_requests() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("poll.json");
+    let url = format!("{}/poll", base);
+    let poll = |client: &ReplayClient| client.get(&url).send().map(|r| r.body_to_utf8().unwrap());
+
+    // Every response is recorded.
+    let client = ReplayClient::new(RecordingTarget::file(&file)).when_exhausted(Exhausted::Record);
+    assert_eq!(poll(&client).unwrap(), "poll 1");
+    assert_eq!(poll(&client).unwrap(), "poll 2");
+    assert_eq!(poll(&client).unwrap(), "poll 3");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    // They are replayed in order, and the last one repeated by default.
+    let client = ReplayClient::new(RecordingTarget::file(&file));
+    assert_eq!(poll(&client).unwrap(), "poll 1");
+    assert_eq!(poll(&client).unwrap(), "poll 2");
+    assert_eq!(poll(&client).unwrap(), "poll 3");
+    assert_eq!(poll(&client).unwrap(), "poll 3");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    let client = ReplayClient::new(RecordingTarget::file(&file))
+        .record_mode(RecordMode::ReplayOnly)
+        .when_exhausted(Exhausted::Error);
+    for _ in 0..3 {
+        poll(&client).unwrap();
+    }
+    match *poll(&client).unwrap_err().kind() {
+        ErrorKind::ReplayExhausted { ref path, recorded } => {
+            assert_eq!((path, recorded), (&file, 3))
+        }
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    // Further responses are added to the recording.
+    let client = ReplayClient::new(RecordingTarget::file(&file)).when_exhausted(Exhausted::Record);
+    for _ in 0..3 {
+        poll(&client).unwrap();
+    }
+    assert_eq!(poll(&client).unwrap(), "poll 4");
+    assert_eq!(poll(&client).unwrap(), "poll 5");
+    assert_eq!(hits.load(Ordering::SeqCst), 5);
+    let client = ReplayClient::new(RecordingTarget::file(&file)).when_exhausted(Exhausted::Error);
+    for i in 1..6 {
+        assert_eq!(poll(&client).unwrap(), format!("poll {}", i));
+    }
+    assert!(poll(&client).is_err());
+
+    // Recording again discards all of them.
+    let client = ReplayClient::new(RecordingTarget::file(&file)).when_exhausted(Exhausted::Record);
+    client.force_record_next();
+    assert_eq!(poll(&client).unwrap(), "poll 6");
+    assert_eq!(poll(&client).unwrap(), "poll 7");
+    let client = ReplayClient::new(RecordingTarget::file(&file)).when_exhausted(Exhausted::Error);
+    assert_eq!(poll(&client).unwrap(), "poll 6");
+    assert_eq!(poll(&client).unwrap(), "poll 7");
+    assert!(poll(&client).is_err());
+}
+
+#[test]
+fn exhausted_old_file_name() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/poll", base);
+    let client = |naming, exhausted| {
+        let mut client = ReplayClient::new(RecordingTarget::dir(dir.path()))
+            .file_naming(naming)
+            .when_exhausted(exhausted);
+        client.config_mut().max_buffered_body = Some(1);
+        client
+    };
+    let poll = |client: &ReplayClient| client.get(&url).send().map(|r| r.body_to_utf8().unwrap());
+
+    let hashed = client(FileNaming::Hash, Exhausted::Record);
+    assert_eq!(poll(&hashed).unwrap(), "poll 1");
+    assert_eq!(poll(&hashed).unwrap(), "poll 2");
+
+    // The recording is still read from its old name, and moved with all of its responses when
+    // one is added.
+    let readable = client(FileNaming::Readable, Exhausted::Record);
+    assert_eq!(poll(&readable).unwrap(), "poll 1");
+    assert_eq!(poll(&readable).unwrap(), "poll 2");
+    assert_eq!(poll(&readable).unwrap(), "poll 3");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    let root_files: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| !name.starts_with('.') && name != "index.json")
+        .collect();
+    assert_eq!(root_files.len(), 1);
+    assert!(root_files[0].starts_with("127.0.0.1_"));
+
+    let readable = client(FileNaming::Readable, Exhausted::Error);
+    for i in 1..4 {
+        assert_eq!(poll(&readable).unwrap(), format!("poll {}", i));
+    }
+    assert!(poll(&readable).is_err());
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn exhausted_legacy_file_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = |body: &str, exhausted| {
+        let mut stub = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::MethodUrl,
+        });
+        stub.stub(Url::parse("http://example.com/").unwrap())
+            .method(Method::GET)
+            .response()
+            .body(body)
+            .mock()
+            .unwrap();
+        ReplayClient::with_client(RecordingTarget::dir(dir.path()), stub).when_exhausted(exhausted)
+    };
+    let get = |client: &ReplayClient<StubClient>| {
+        client
+            .get("http://example.com/")
+            .send()
+            .map(|r| r.body_to_utf8().unwrap())
+    };
+
+    assert_eq!(get(&client("first", Exhausted::Error)).unwrap(), "first");
+    let recorded = |dir: &tempfile::TempDir| -> Vec<_> {
+        fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.starts_with('.') && name != "index.json")
+            .collect()
+    };
+    let hashed = recorded(&dir);
+    assert_eq!(hashed.len(), 1);
+    // The name of the recording before the format version 4.
+    let legacy = dir.path().join("8ab80ed3dea0c0e8.json");
+    fs::rename(dir.path().join(&hashed[0]), &legacy).unwrap();
+
+    let appending = client("second", Exhausted::Record);
+    assert_eq!(get(&appending).unwrap(), "first");
+    assert_eq!(get(&appending).unwrap(), "second");
+    assert!(!legacy.exists());
+    assert_eq!(recorded(&dir), hashed);
+
+    let replaying = client("third", Exhausted::Error);
+    assert_eq!(get(&replaying).unwrap(), "first");
+    assert_eq!(get(&replaying).unwrap(), "second");
+    assert!(get(&replaying).is_err());
+}
+
+#[test]
+fn inner_client() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = || RecordingTarget::dir(dir.path());
+    let stub_settings = |default| StubSettings {
+        default,
+        strictness: StubStrictness::MethodUrl,
+    };
+
+    // Recording a stubbed response.
+    let mut stub = StubClient::new(stub_settings(StubDefault::Error));
+    stub.stub(Url::parse("http://example.com/recorded").unwrap())
+        .method(Method::GET)
+        .response()
+        .body("recorded")
+        .mock()
+        .unwrap();
+    let client = ReplayClient::with_client(target(), stub);
+    let response = client.get("http://example.com/recorded").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "recorded");
+    assert!(client.get("http://example.com/missing").send().is_err());
+
+    // Stubs falling back to the recording.
+    let replay = ReplayClient::new(target()).record_mode(RecordMode::ReplayOnly);
+    let mut client = StubClient::with_fallback(stub_settings(StubDefault::PerformRequest), replay);
+    client
+        .stub(Url::parse("http://example.com/stubbed").unwrap())
+        .method(Method::GET)
+        .response()
+        .body("stubbed")
+        .mock()
+        .unwrap();
+    let response = client.get("http://example.com/stubbed").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "stubbed");
+    let response = client.get("http://example.com/recorded").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "recorded");
+    match *client
+        .get("http://example.com/missing")
+        .send()
+        .unwrap_err()
+        .kind()
+    {
+        ErrorKind::ReplayMissing(_) => {}
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+}
+
+#[test]
+fn replay_changed() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("changed.json");
+    let url = format!("{}/end", base);
+
+    let client = ReplayClient::new(RecordingTarget::file(&file));
+    client.get(&url).send().unwrap();
+
+    let client = client.record_mode(RecordMode::ReplayOnly);
+    let error = client
+        .get(format!("{}?page=2", url))
+        .header(ACCEPT, "text/plain".parse().unwrap())
+        .send()
+        .unwrap_err();
+    match *error.kind() {
+        ErrorKind::ReplayChanged { ref path, ref diff } => {
+            assert_eq!(path, &file);
+            let fields: Vec<&str> = diff.fields().iter().map(|f| &f.field[..]).collect();
+            assert_eq!(fields, ["query \"page\"", "header \"accept\""]);
+        }
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert!(error
+        .to_string()
+        .contains("header \"accept\": expected nothing, got text/plain"));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn replay_changed_dir() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/end", base);
+
+    let client = ReplayClient::new(RecordingTarget::dir(dir.path()));
+    client.get(format!("{}?page=1", url)).send().unwrap();
+    client.get(format!("{}/other", base)).send().unwrap();
+    let recorded = client.used_recordings();
+
+    // The changed request is named differently, the recording of the same URL path is found.
+    let client = client.record_mode(RecordMode::ReplayOnly);
+    let error = client
+        .get(format!("{}?page=2", url))
+        .header(ACCEPT, "text/plain".parse().unwrap())
+        .send()
+        .unwrap_err();
+    match *error.kind() {
+        ErrorKind::ReplayChanged { ref path, ref diff } => {
+            assert!(recorded.contains(path));
+            let fields: Vec<&str> = diff.fields().iter().map(|f| &f.field[..]).collect();
+            assert_eq!(fields, ["query \"page\"", "header \"accept\""]);
+        }
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    // Requests of another URL path are only missing.
+    match *client
+        .get(format!("{}/new", base))
+        .send()
+        .unwrap_err()
+        .kind()
+    {
+        ErrorKind::ReplayMissing(_) => {}
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+/// A recording of format version 3, which stored the request body as bytes and the
+/// response body base64 encoded.
+const V3_RECORDING: &str = r#"{
+    "request": {
+        "url": "http://example.com/v3",
+        "method": "POST",
+        "body": [123, 34, 97, 34, 58, 49, 125],
+        "headers": {}
+    },
+    "response": {
+        "url": "http://example.com/v3",
+        "status": 200,
+        "headers": {},
+        "body": "aGVsbG8="
+    },
+    "format_version": 3
+}"#;
+
+#[test]
+fn migrate_v3() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("v3.json");
+    fs::write(&file, V3_RECORDING).unwrap();
+
+    // Replaying works without recording again.
+    let client =
+        ReplayClient::new(RecordingTarget::file(&file)).record_mode(RecordMode::ReplayOnly);
+    let response = client
+        .post("http://example.com/v3")
+        .body(r#"{"a":1}"#)
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "hello");
+}
+
+#[test]
+fn upgrade_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    // The name of the recording before the names were stable.
+    let file = dir.path().join("3658d705edee7db5.json");
+    let recording = V3_RECORDING.replace(
+        "\"format_version\": 3",
+        "\"body_file\": \"3658d705edee7db5.0.body\", \"format_version\": 3",
+    );
+    fs::write(&file, recording).unwrap();
+    fs::write(
+        dir.path().join("3658d705edee7db5.0.body"),
+        "hello from file",
+    )
+    .unwrap();
+    fs::write(dir.path().join("notes.txt"), "not a recording").unwrap();
+
+    // The file is migrated and renamed to the name of its request.
+    let target = RecordingTarget::dir(dir.path());
+    assert_eq!(target.upgrade().unwrap(), 1);
+    assert!(!file.exists());
+    let file = dir.path().join("173a79afdcc744b2.json");
+    let content = fs::read_to_string(&file).unwrap();
+    assert!(content.contains("\"format_version\": 5"));
+    assert!(content.contains("\"body_json\": {\n      \"a\": 1\n    }"));
+    assert!(content.contains("\"body_file\": \"173a79afdcc744b2.0.body\""));
+    assert_eq!(target.upgrade().unwrap(), 0);
+
+    let client = ReplayClient::new(target).record_mode(RecordMode::ReplayOnly);
+    let response = client
+        .post("http://example.com/v3")
+        .body(r#"{"a":1}"#)
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "hello from file");
+}
+
+#[test]
+fn custom_root_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = |name: &'static str| {
+        let mut stub = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::MethodUrl,
+        });
+        stub.stub(Url::parse("http://example.com/").unwrap())
+            .method(Method::GET)
+            .response()
+            .body("custom")
+            .mock()
+            .unwrap();
+        ReplayClient::with_client(RecordingTarget::dir(dir.path()), stub)
+            .file_naming(FileNaming::custom(move |_| PathBuf::from(name)))
+    };
+
+    // The name of the index is reserved.
+    let error = client("index")
+        .get("http://example.com/")
+        .send()
+        .unwrap_err();
+    match *error.kind() {
+        ErrorKind::InvalidReplayPath(ref path) => assert!(path.ends_with("index.json")),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    // Upgrading keeps names chosen by the client.
+    let response = client("custom").get("http://example.com/").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "custom");
+    assert_eq!(RecordingTarget::dir(dir.path()).upgrade().unwrap(), 0);
+    assert!(dir.path().join("custom.json").exists());
+}
+
+#[test]
+fn newer_format_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("future.json");
+    let content = V3_RECORDING.replace("\"format_version\": 3", "\"format_version\": 99");
+    fs::write(&file, &content).unwrap();
+
+    // Even when recording, the file is not replaced.
+    let client = ReplayClient::new(RecordingTarget::file(&file));
+    let error = client
+        .post("http://example.com/v3")
+        .body(r#"{"a":1}"#)
+        .send()
+        .unwrap_err();
+    match *error.kind() {
+        ErrorKind::ReplayFormatMismatch {
+            found, expected, ..
+        } => assert_eq!((found, expected), (Some(99), 5)),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert!(error
+        .to_string()
+        .ends_with("written by a newer version of reqwest_mock"));
+    assert!(RecordingTarget::file(&file).upgrade().is_err());
+    assert_eq!(fs::read_to_string(&file).unwrap(), content);
+}
+
+#[test]
+fn large_body_digest() {
+    let server = helper::run_server("127.0.0.1:19251".parse().unwrap());
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("large.json");
+    let url = "http://127.0.0.1:19251/large";
+
+    // Every body longer than one byte is identified by its digest.
+    let mut client = ReplayClient::new(RecordingTarget::file(&file));
+    client.config_mut().max_buffered_body = Some(1);
+    let response = client
+        .post(url)
+        .body(Body::from_reader(Cursor::new("41"), None))
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap().lines().next(), Some("42"));
+    server.terminate();
+
+    let recorded = fs::read_to_string(&file).unwrap();
+    assert!(!recorded.contains(r#""body": "41""#));
+    assert!(recorded.contains("body_digest"));
+
+    // The same content is replayed, no matter where it is read from.
+    let response = client.post(url).body("41").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap().lines().next(), Some("42"));
+}
+
+#[test]
+fn large_response_body() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("large.json");
+    let url = format!("{}/start", base);
+
+    let mut client = ReplayClient::new(RecordingTarget::file(&file));
+    client.config_mut().max_buffered_body = Some(4);
+    let mut response = client.get(&url).send_streaming().unwrap();
+    let mut body = String::new();
+    response.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "/end");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    // Only the bodies longer than the limit are stored separately.
+    let body_file = |index| dir.path().join(format!("large.{}.body", index));
+    assert_eq!(fs::read_to_string(body_file(0)).unwrap(), "/start");
+    assert_eq!(fs::read_to_string(body_file(1)).unwrap(), "/middle");
+    assert!(!body_file(2).exists());
+    let recorded = fs::read_to_string(&file).unwrap();
+    assert!(recorded.contains(r#""body_file": "large.0.body""#));
+
+    // Replaying streams the bodies from the files.
+    let mut client = ReplayClient::new(RecordingTarget::file(&file));
+    client.config_mut().redirect = RedirectPolicy::None;
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "/start");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+    // A missing body file is recorded again.
+    fs::remove_file(body_file(0)).unwrap();
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "/start");
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn readable_file_names() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let host = base.trim_start_matches("http://").replace(':', "_");
+    let url = format!("{}/end/Items?page=1", base);
+
+    let client = ReplayClient::new(RecordingTarget::dir(dir.path()))
+        .file_naming(FileNaming::Readable)
+        .subdir("my_test");
+    client.get(&url).send().unwrap();
+
+    let files: Vec<_> = fs::read_dir(dir.path().join("my_test").join(&host))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].starts_with("GET_end-items_"), "{}", files[0]);
+    assert!(files[0].ends_with(".json"), "{}", files[0]);
+
+    // The index maps the file to the request.
+    let index = fs::read_to_string(dir.path().join("index.json")).unwrap();
+    assert!(index.contains(&format!("\"my_test/{}/{}\"", host, files[0])));
+    assert!(index.contains("\"method\": \"GET\""));
+    assert!(index.contains(&format!("\"url\": \"{}\"", url)));
+
+    // The recording is found again.
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "/end/Items?page=1");
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // Upgrading keeps the files in subdirectories in place, and the index.
+    assert_eq!(RecordingTarget::dir(dir.path()).upgrade().unwrap(), 0);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("index.json")).unwrap(),
+        index
+    );
+}
+
+#[test]
+fn prune_unused() {
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let target = RecordingTarget::dir(dir.path());
+    let client = |track| {
+        let mut client = ReplayClient::new(RecordingTarget::dir(dir.path())).track_usage(track);
+        client.config_mut().max_buffered_body = Some(1);
+        client
+    };
+
+    let recorder = client(false);
+    recorder.get(format!("{}/kept", base)).send().unwrap();
+    recorder.get(format!("{}/stale", base)).send().unwrap();
+    let recorded = recorder.used_recordings();
+    assert_eq!(recorded.len(), 2);
+    assert!(recorder.unused_recordings().unwrap().is_empty());
+
+    // Without tracked usage every file would be unused.
+    match *target.unused().unwrap_err().kind() {
+        ErrorKind::NoUsageRecorded(ref path) => assert_eq!(path, dir.path()),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert!(target.prune(false).is_err());
+
+    let tracked = client(true);
+    tracked.get(format!("{}/kept", base)).send().unwrap();
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    let kept = tracked.used_recordings();
+    assert_eq!(kept.len(), 1);
+    let stale: Vec<_> = recorded.into_iter().filter(|f| *f != kept[0]).collect();
+    assert_eq!(tracked.unused_recordings().unwrap(), stale);
+    assert_eq!(target.unused().unwrap(), stale);
+
+    // A dry run deletes nothing.
+    assert_eq!(target.prune(true).unwrap(), stale);
+    assert!(stale[0].exists());
+
+    let body_file = |file: &PathBuf| file.with_extension("0.body");
+    assert!(body_file(&stale[0]).exists());
+    assert_eq!(target.prune(false).unwrap(), stale);
+    assert!(!stale[0].exists());
+    assert!(!body_file(&stale[0]).exists());
+    assert!(kept[0].exists());
+    assert!(body_file(&kept[0]).exists());
+    let index = fs::read_to_string(dir.path().join("index.json")).unwrap();
+    assert!(index.contains("/kept"));
+    assert!(!index.contains("/stale"));
+
+    // Pruning resets the usage.
+    assert!(target.unused().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn read_only_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (base, hits) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/end", base);
+    let client = ReplayClient::new(RecordingTarget::dir(dir.path()));
+    client.get(&url).send().unwrap();
+
+    // Replaying only needs read access, even without a lock file.
+    fs::remove_file(dir.path().join(".reqwest_mock.lock")).unwrap();
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o555)).unwrap();
+    let client =
+        ReplayClient::new(RecordingTarget::dir(dir.path())).record_mode(RecordMode::ReplayOnly);
+    let result = client.get(&url).send();
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(result.unwrap().body_to_utf8().unwrap(), "/end");
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn concurrent_recording() {
+    let (base, _) = run_redirect_server();
+    let dir = tempfile::tempdir().unwrap();
+
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let base = base.clone();
+            let dir = dir.path().to_path_buf();
+            thread::spawn(move || {
+                let mut client = ReplayClient::new(RecordingTarget::dir(&dir));
+                // Half of the clients store the bodies in separate files.
+                if i % 2 == 0 {
+                    client.config_mut().max_buffered_body = Some(1);
+                }
+                for j in 0..12 {
+                    let path = format!("/end{}", j % 4);
+                    if (i + j) % 3 == 0 {
+                        client.force_record_next();
+                    }
+                    let response = client.get(format!("{}{}", base, path)).send().unwrap();
+                    assert_eq!(response.body_to_utf8().unwrap(), path);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // Only complete recordings and no temporary files are left.
+    let mut recordings = 0;
+    for entry in fs::read_dir(dir.path()).unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        if name == "index.json" {
+            continue;
+        } else if name.ends_with(".json") {
+            recordings += 1;
+        } else {
+            assert!(
+                name.ends_with(".body") || name == ".reqwest_mock.lock",
+                "unexpected file {}",
+                name
+            );
+        }
+    }
+    assert_eq!(recordings, 4);
+    let index = fs::read_to_string(dir.path().join("index.json")).unwrap();
+    assert_eq!(index.matches("\"hash\"").count(), 4);
+    assert_eq!(RecordingTarget::dir(dir.path()).upgrade().unwrap(), 0);
+}
@@ -0,0 +1,198 @@
+//! Test the `tower` integration.
+
+extern crate axum;
+extern crate bytes;
+extern crate http;
+extern crate http_body;
+extern crate reqwest_mock;
+extern crate tokio;
+extern crate tower_service;
+
+use bytes::Bytes;
+use http_body::Full;
+use reqwest_mock::header::LOCATION;
+use reqwest_mock::{
+    Body, Client, ClientService, DirectClient, Method, ServiceClient, StatusCode, StubClient,
+    StubDefault, StubSettings, StubStrictness, Url,
+};
+use std::convert::Infallible;
+use std::future::{ready, Future, Ready};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::Builder;
+use tokio::time::Sleep;
+use tower_service::Service;
+
+#[test]
+fn client_as_service() {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::MethodUrl,
+    });
+    client
+        .stub(Url::parse("http://example.com/hello").unwrap())
+        .method(Method::GET)
+        .response()
+        .body("Hello")
+        .mock()
+        .unwrap();
+
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let _runtime = runtime.enter();
+    let mut service = ClientService::new(client);
+
+    let request = http::Request::get("http://example.com/hello")
+        .body(Body::from(""))
+        .unwrap();
+    let response = runtime.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body(), b"Hello");
+
+    let request = http::Request::get("http://example.com/missing")
+        .body(Body::from(""))
+        .unwrap();
+    assert!(runtime.block_on(service.call(request)).is_err());
+}
+
+/// Run a server on a random port responding with the path, after `delay` for `/slow`.
+fn run_slow_server(delay: Duration) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let request_line = BufReader::new(stream.try_clone().unwrap())
+                .lines()
+                .next()
+                .unwrap()
+                .unwrap();
+            let path = request_line.split(' ').nth(1).unwrap().to_string();
+            if path == "/slow" {
+                thread::sleep(delay);
+            }
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                path.len(),
+                path
+            );
+        }
+    });
+    base
+}
+
+#[test]
+fn direct_client_as_service() {
+    let base = run_slow_server(Duration::from_millis(500));
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let _runtime = runtime.enter();
+    let mut service = ClientService::new(DirectClient::new());
+
+    // The blocking client runs outside of the runtime.
+    let request = http::Request::get(format!("{}/fast", base))
+        .body(Body::from(""))
+        .unwrap();
+    let response = runtime.block_on(service.call(request)).unwrap();
+    assert_eq!(response.body(), b"/fast");
+
+    // The runtime isn't blocked by the request, so its timers can interrupt it.
+    let request = http::Request::get(format!("{}/slow", base))
+        .body(Body::from(""))
+        .unwrap();
+    let timeout = tokio::time::timeout(Duration::from_millis(50), service.call(request));
+    assert!(runtime.block_on(timeout).is_err());
+}
+
+/// A service redirecting `/old` to `/new`, which echoes the request body.
+#[derive(Clone)]
+struct Router;
+
+impl Service<http::Request<Full<Bytes>>> for Router {
+    type Response = http::Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<Full<Bytes>>) -> Self::Future {
+        let response = match request.uri().path() {
+            "/old" => http::Response::builder()
+                .status(StatusCode::TEMPORARY_REDIRECT)
+                .header(LOCATION, "/new")
+                .body(Full::default()),
+            "/new" => http::Response::builder().body(request.into_body()),
+            _ => http::Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Full::default()),
+        };
+        ready(Ok(response.unwrap()))
+    }
+}
+
+#[test]
+fn service_as_client() {
+    let client: ServiceClient<Router, Full<Bytes>> = ServiceClient::new(Router);
+
+    let response = client
+        .post("http://example.com/old")
+        .body("echo")
+        .send()
+        .unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.url.as_str(), "http://example.com/new");
+    assert_eq!(response.body_to_utf8().unwrap(), "echo");
+
+    let response = client.get("http://example.com/other").send().unwrap();
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+/// Responds with `body` after a short sleep, which needs the timer of a `tokio` runtime.
+struct Delayed {
+    sleep: Pin<Box<Sleep>>,
+    body: &'static str,
+}
+
+impl Future for Delayed {
+    type Output = &'static str;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<&'static str> {
+        match self.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(self.body),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[test]
+fn axum_router() {
+    let router = axum::Router::new()
+        .route(
+            "/slow",
+            axum::routing::get(|| Delayed {
+                sleep: Box::pin(tokio::time::sleep(Duration::from_millis(10))),
+                body: "slept",
+            }),
+        )
+        .route("/echo", axum::routing::post(|body: String| ready(body)));
+    let client: ServiceClient<_, axum::body::Body> = ServiceClient::new(router);
+
+    let response = client.get("http://example.com/slow").send().unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body_to_utf8().unwrap(), "slept");
+
+    let response = client
+        .post("http://example.com/echo")
+        .body("echo")
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "echo");
+
+    let response = client.get("http://example.com/other").send().unwrap();
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
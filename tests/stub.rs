@@ -0,0 +1,405 @@
+//! Test the `StubClient`.
+
+extern crate reqwest_mock;
+extern crate tempfile;
+
+use reqwest_mock::config::{ClientConfig, RedirectPolicy};
+use reqwest_mock::error::ErrorKind;
+use reqwest_mock::header::{HeaderMap, ACCEPT, LOCATION, REFERER};
+use reqwest_mock::multipart::{Form, Part, PartMatcher};
+use reqwest_mock::{
+    Body, Client, Method, StatusCode, StubClient, StubDefault, StubSettings, StubStrictness, Url,
+};
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+
+#[test]
+fn config_defaults() {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::HeadersMethodUrl,
+    });
+    *client.config_mut() = ClientConfig::builder()
+        .base_url(Url::parse("http://example.com/api/").unwrap())
+        .user_agent("MyClient/1.0".parse().unwrap())
+        .build();
+
+    // The stub does not mention the user agent, it is added from the config.
+    client
+        .stub(Url::parse("http://example.com/api/users").unwrap())
+        .method(Method::GET)
+        .header(ACCEPT, "application/json".parse().unwrap())
+        .response()
+        .body("[]")
+        .mock()
+        .unwrap();
+
+    let response = client
+        .get("users")
+        .header(ACCEPT, "application/json".parse().unwrap())
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "[]");
+
+    // Overriding the user agent makes the request differ from the stub.
+    let response = client
+        .get("users")
+        .header(ACCEPT, "application/json".parse().unwrap())
+        .header(reqwest_mock::header::USER_AGENT, "Other".parse().unwrap())
+        .send();
+    match *response.unwrap_err().kind() {
+        ErrorKind::NoStubMatched {
+            ref method,
+            ref url,
+            ref headers,
+            ..
+        } => {
+            assert_eq!(method, Method::GET);
+            assert_eq!(url.as_str(), "http://example.com/api/users");
+            assert_eq!(headers[reqwest_mock::header::USER_AGENT], "Other");
+        }
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    // A stub without headers of its own has the default ones as well.
+    client
+        .stub(Url::parse("http://example.com/api/groups").unwrap())
+        .method(Method::GET)
+        .response()
+        .body("{}")
+        .mock()
+        .unwrap();
+    let response = client.get("groups").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "{}");
+}
+
+fn redirect_client(policy: RedirectPolicy) -> StubClient {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::MethodUrl,
+    });
+    client.config_mut().redirect = policy;
+
+    for &(from, to) in &[
+        ("http://a.example.com/1", "/2"),
+        ("http://a.example.com/2", "http://b.example.com/3"),
+    ] {
+        client
+            .stub(Url::parse(from).unwrap())
+            .method(Method::GET)
+            .response()
+            .status_code(StatusCode::FOUND)
+            .header(LOCATION, to.parse().unwrap())
+            .mock()
+            .unwrap();
+    }
+    client
+        .stub(Url::parse("http://b.example.com/3").unwrap())
+        .method(Method::GET)
+        .response()
+        .body("done")
+        .mock()
+        .unwrap();
+
+    client
+}
+
+#[test]
+fn redirect_followed() {
+    let client = redirect_client(RedirectPolicy::default());
+    let response = client.get("http://a.example.com/1").send().unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.url.as_str(), "http://b.example.com/3");
+    assert_eq!(response.body_to_utf8().unwrap(), "done");
+}
+
+#[test]
+fn redirect_policy_none() {
+    let client = redirect_client(RedirectPolicy::None);
+    let response = client.get("http://a.example.com/1").send().unwrap();
+    assert_eq!(response.status, StatusCode::FOUND);
+    assert_eq!(response.headers[LOCATION], "/2");
+}
+
+#[test]
+fn redirect_policy_limit() {
+    let client = redirect_client(RedirectPolicy::Limit(2));
+    assert!(client.get("http://a.example.com/1").send().is_err());
+}
+
+#[test]
+fn redirect_policy_same_origin() {
+    let client = redirect_client(RedirectPolicy::SameOrigin(10));
+    let response = client.get("http://a.example.com/1").send().unwrap();
+    assert_eq!(response.status, StatusCode::FOUND);
+    assert_eq!(response.url.as_str(), "http://a.example.com/2");
+}
+
+#[test]
+fn redirect_policy_custom() {
+    let client = redirect_client(RedirectPolicy::custom(|attempt| {
+        if attempt.url().path() == "/3" {
+            attempt.error("not allowed")
+        } else {
+            attempt.follow()
+        }
+    }));
+    assert!(client.get("http://a.example.com/1").send().is_err());
+}
+
+#[test]
+fn redirect_referer() {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::HeadersMethodUrl,
+    });
+    client
+        .stub(Url::parse("http://example.com/old").unwrap())
+        .method(Method::GET)
+        .headers(HeaderMap::new())
+        .response()
+        .status_code(StatusCode::MOVED_PERMANENTLY)
+        .header(LOCATION, "/new".parse().unwrap())
+        .mock()
+        .unwrap();
+    client
+        .stub(Url::parse("http://example.com/new").unwrap())
+        .method(Method::GET)
+        .header(REFERER, "http://example.com/old".parse().unwrap())
+        .response()
+        .body("moved")
+        .mock()
+        .unwrap();
+
+    let response = client.get("http://example.com/old").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "moved");
+
+    // Without the referer the request does not match the stub anymore.
+    client.config_mut().referer = false;
+    assert!(client.get("http://example.com/old").send().is_err());
+}
+
+#[test]
+fn multipart_body() {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::BodyMethodUrl,
+    });
+    let form = || {
+        Form::new()
+            .text("title", "Holiday")
+            .part("photo", Part::bytes(vec![1, 2, 3]).file_name("beach.jpg"))
+    };
+    client
+        .stub(Url::parse("http://example.com/upload").unwrap())
+        .method(Method::POST)
+        .body(form())
+        .response()
+        .body("exact")
+        .mock()
+        .unwrap();
+
+    // The serialization is deterministic, so the exact body matches.
+    let response = client
+        .post("http://example.com/upload")
+        .multipart(form())
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "exact");
+
+    let other = form().text("tags", "beach");
+    assert!(client
+        .post("http://example.com/upload")
+        .multipart(other)
+        .send()
+        .is_err());
+}
+
+#[test]
+fn multipart_parts() {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::BodyMethodUrl,
+    });
+    client
+        .stub(Url::parse("http://example.com/upload").unwrap())
+        .method(Method::POST)
+        .part(PartMatcher::new("title").text("Holiday"))
+        .part(PartMatcher::new("photo").file_name("beach.jpg"))
+        .response()
+        .body("parts")
+        .mock()
+        .unwrap();
+
+    let form = Form::new()
+        .text("tags", "beach")
+        .text("title", "Holiday")
+        .part("photo", Part::bytes(vec![1, 2, 3]).file_name("beach.jpg"));
+    let response = client
+        .post("http://example.com/upload")
+        .multipart(form)
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "parts");
+
+    // Every matcher has to match a part.
+    let form = Form::new().text("title", "Holiday");
+    assert!(client
+        .post("http://example.com/upload")
+        .multipart(form)
+        .send()
+        .is_err());
+
+    // Bodies which are not multipart never match part matchers.
+    assert!(client
+        .post("http://example.com/upload")
+        .body("title=Holiday")
+        .send()
+        .is_err());
+}
+
+#[test]
+fn reader_body() {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::BodyMethodUrl,
+    });
+    client.config_mut().max_buffered_body = Some(4);
+    client
+        .stub(Url::parse("http://example.com/upload").unwrap())
+        .method(Method::POST)
+        .body("0123456789")
+        .response()
+        .body("uploaded")
+        .mock()
+        .unwrap();
+
+    // The body exceeds the limit, so it is matched by its digest.
+    let response = client
+        .post("http://example.com/upload")
+        .body(Body::from_reader(Cursor::new("0123456789"), None))
+        .send()
+        .unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "uploaded");
+
+    assert!(client
+        .post("http://example.com/upload")
+        .body(Body::from_reader(Cursor::new("0123456780"), Some(10)))
+        .send()
+        .is_err());
+}
+
+#[test]
+fn file_response_body() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"file contents").unwrap();
+
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::Url,
+    });
+    client
+        .stub(Url::parse("http://example.com/download").unwrap())
+        .response()
+        .body(File::open(file.path()).unwrap())
+        .mock()
+        .unwrap();
+
+    // The file is read again for every request.
+    for _ in 0..2 {
+        let mut response = client
+            .get("http://example.com/download")
+            .send_streaming()
+            .unwrap();
+        let mut body = String::new();
+        response.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "file contents");
+    }
+
+    let response = client.get("http://example.com/download").send().unwrap();
+    assert_eq!(response.body_to_utf8().unwrap(), "file contents");
+}
+
+#[test]
+fn register_stub_error() {
+    fn register(client: &mut StubClient) -> Result<(), reqwest_mock::Error> {
+        client
+            .stub(Url::parse("http://example.com/").unwrap())
+            .response()
+            .mock()?;
+        Ok(())
+    }
+
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::MethodUrl,
+    });
+    let error = register(&mut client).unwrap_err();
+    match *error.kind() {
+        ErrorKind::RegisterStub => {}
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert_eq!(error.to_string(), "registering stub failed");
+    assert_eq!(
+        StdError::source(&error).unwrap().to_string(),
+        "Tried registering stub without `method` even though \
+         `StubStrictness::MethodUrl` requires its presence."
+    );
+
+    let error = client
+        .stub(Url::parse("http://example.com/").unwrap())
+        .method(Method::GET)
+        .header(ACCEPT, "text/plain".parse().unwrap())
+        .response()
+        .mock()
+        .unwrap_err();
+    assert_eq!(error.field_name(), Some("headers"));
+    assert!(error.to_string().contains("set a higher `StubStrictness`"));
+}
+
+#[test]
+fn no_stub_matched_closest() {
+    let mut client = StubClient::new(StubSettings {
+        default: StubDefault::Error,
+        strictness: StubStrictness::BodyMethodUrl,
+    });
+    for &(path, body) in &[
+        ("users", r#"{"name": "Jane", "admin": false}"#),
+        ("users", "name=Jane"),
+        ("groups", r#"{"name": "Jane"}"#),
+    ] {
+        client
+            .stub(Url::parse(&format!("http://example.com/{}", path)).unwrap())
+            .method(Method::POST)
+            .body(body)
+            .response()
+            .mock()
+            .unwrap();
+    }
+
+    let error = client
+        .post("http://example.com/users")
+        .body(r#"{"name": "Jane", "admin": true}"#)
+        .send()
+        .unwrap_err();
+    match *error.kind() {
+        ErrorKind::NoStubMatched { ref closest, .. } => {
+            assert_eq!(closest.len(), 3);
+            assert_eq!(closest[0].fields().len(), 1);
+            assert_eq!(closest[0].fields()[0].field, "body /admin");
+        }
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    assert_eq!(
+        error.to_string(),
+        "Requested POST http://example.com/users, without having provided a stub for it.\n\
+         Closest stub #1 (POST http://example.com/users):\n    \
+         body /admin: expected false, got true\n\
+         Closest stub #2 (POST http://example.com/users):\n    \
+         body: expected \"name=Jane\", got \"{\\\"name\\\": \\\"Jane\\\", \\\"admin\\\": true}\"\n\
+         Closest stub #3 (POST http://example.com/groups):\n    \
+         url: expected http://example.com/groups, got http://example.com/users\n    \
+         body /admin: expected nothing, got true"
+    );
+}
@@ -0,0 +1,228 @@
+//! Test the TLS settings of `ClientConfig` against a local TLS server.
+//!
+//! A fresh test CA is generated for each test, which signs the certificate of the
+//! server as well as the one of the client (used for mutual TLS).
+//!
+//! The server answers every request with `200 OK` and a plaintext body, which is
+//! either `anonymous` or the common name of the certificate the client presented.
+
+extern crate openssl;
+extern crate rcgen;
+extern crate reqwest_mock;
+
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode, SslVersion};
+use openssl::x509::X509;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair, KeyUsagePurpose,
+};
+use reqwest_mock::client::DirectClient;
+use reqwest_mock::config::ClientConfig;
+use reqwest_mock::error::ErrorKind;
+use reqwest_mock::{tls, Client, Identity, StatusCode};
+use std::error::Error as StdError;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+struct TestCa {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl TestCa {
+    fn new() -> Self {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "reqwest_mock test CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        let cert = params.self_signed(&key).unwrap();
+        TestCa { cert, key }
+    }
+
+    /// Issue a leaf certificate, returning the certificate and key in PEM format.
+    fn issue(&self, common_name: &str) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn root_certificate(&self) -> reqwest_mock::Certificate {
+        reqwest_mock::Certificate::from_pem(self.cert.pem().as_bytes()).unwrap()
+    }
+}
+
+#[derive(Default)]
+struct ServerOptions {
+    require_client_cert: bool,
+    max_tls_version: Option<SslVersion>,
+}
+
+/// Run a TLS server on a random port and return the URL it can be reached at.
+fn run_tls_server(ca: &TestCa, options: ServerOptions) -> String {
+    let (cert_pem, key_pem) = ca.issue("localhost");
+
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+    acceptor
+        .set_certificate(&X509::from_pem(cert_pem.as_bytes()).unwrap())
+        .unwrap();
+    acceptor
+        .set_private_key(&PKey::private_key_from_pem(key_pem.as_bytes()).unwrap())
+        .unwrap();
+    acceptor
+        .set_max_proto_version(options.max_tls_version)
+        .unwrap();
+    if options.require_client_cert {
+        acceptor
+            .cert_store_mut()
+            .add_cert(X509::from_pem(ca.cert.pem().as_bytes()).unwrap())
+            .unwrap();
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    let acceptor = acceptor.build();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream.map(|s| acceptor.accept(s)) {
+                Ok(Ok(s)) => s,
+                _ => continue,
+            };
+
+            // Read the request head, the tests never send a body.
+            let mut head = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !head.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => head.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let body = stream
+                .ssl()
+                .peer_certificate()
+                .and_then(|cert| {
+                    cert.subject_name()
+                        .entries_by_nid(Nid::COMMONNAME)
+                        .next()
+                        .and_then(|entry| entry.data().to_string().ok())
+                })
+                .unwrap_or_else(|| "anonymous".to_string());
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+            let _ = stream.shutdown();
+        }
+    });
+
+    format!("https://localhost:{}/", port)
+}
+
+fn client(config: ClientConfig) -> DirectClient {
+    let mut client = DirectClient::new();
+    *client.config_mut() = config;
+    client
+}
+
+#[test]
+fn untrusted_ca_is_rejected() {
+    let ca = TestCa::new();
+    let url = run_tls_server(&ca, ServerOptions::default());
+
+    assert!(client(ClientConfig::default()).get(&url).send().is_err());
+}
+
+#[test]
+fn root_certificate() {
+    let ca = TestCa::new();
+    let url = run_tls_server(&ca, ServerOptions::default());
+
+    let mut config = ClientConfig::default();
+    config.root_certificates.push(ca.root_certificate());
+
+    let resp = client(config).get(&url).send().unwrap();
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body_to_utf8().unwrap(), "anonymous");
+}
+
+#[test]
+fn danger_accept_invalid_certs() {
+    let ca = TestCa::new();
+    let url = run_tls_server(&ca, ServerOptions::default());
+
+    let config = ClientConfig {
+        danger_accept_invalid_certs: true,
+        ..ClientConfig::default()
+    };
+
+    let resp = client(config).get(&url).send().unwrap();
+    assert_eq!(resp.status, StatusCode::OK);
+}
+
+#[test]
+fn client_identity() {
+    let ca = TestCa::new();
+    let url = run_tls_server(
+        &ca,
+        ServerOptions {
+            require_client_cert: true,
+            ..ServerOptions::default()
+        },
+    );
+
+    // Without an identity the server refuses the handshake.
+    let mut config = ClientConfig::default();
+    config.root_certificates.push(ca.root_certificate());
+    assert!(client(config.clone()).get(&url).send().is_err());
+
+    let (cert_pem, key_pem) = ca.issue("test client");
+    config.identity =
+        Some(Identity::from_pkcs8_pem(cert_pem.as_bytes(), key_pem.as_bytes()).unwrap());
+
+    let resp = client(config).get(&url).send().unwrap();
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.body_to_utf8().unwrap(), "test client");
+}
+
+#[test]
+fn min_tls_version() {
+    let ca = TestCa::new();
+    let url = run_tls_server(
+        &ca,
+        ServerOptions {
+            max_tls_version: Some(SslVersion::TLS1_2),
+            ..ServerOptions::default()
+        },
+    );
+
+    let mut config = ClientConfig::default();
+    config.root_certificates.push(ca.root_certificate());
+
+    config.min_tls_version = Some(tls::Version::TLS_1_2);
+    let resp = client(config.clone()).get(&url).send().unwrap();
+    assert_eq!(resp.status, StatusCode::OK);
+
+    // The native-tls backend can't require TLS 1.3, so the client can't be built.
+    config.min_tls_version = Some(tls::Version::TLS_1_3);
+    let error = client(config).get(&url).send().unwrap_err();
+    match *error.kind() {
+        ErrorKind::InvalidRequest => {}
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+    let source = StdError::source(&error).unwrap();
+    assert!(source.to_string().contains("invalid minimum TLS version"));
+}